@@ -19,12 +19,23 @@ struct Args {
     /// Sketch size
     #[clap(short, default_value_t = 10000)]
     s: usize,
-    /// Store bottom-b bits of each element. Must be multiple of 8.
+    /// Store bottom-b bits of each element. Must be 1, 8, 16, 32, or 64.
     #[clap(short, default_value_t = 32)]
     b: usize,
+    /// Width of the k-mer hashes: 32 or 64.
+    #[clap(long, default_value_t = 32)]
+    hash_bits: usize,
+    /// Skip k-mers overlapping non-ACGT characters, such as runs of N.
+    #[clap(long)]
+    skip_non_acgt: bool,
 
     #[clap(long)]
     stats: Option<PathBuf>,
+
+    /// Output `path1 path2 distance ani p-value shared/total` lines, as Mash does,
+    /// instead of only the similarity.
+    #[clap(long)]
+    mash: bool,
 }
 
 fn main() {
@@ -40,33 +51,29 @@ fn main() {
 
     let mut sketcher = simd_sketch::Sketcher::new_rc(k, s, b);
     sketcher.filter_empty = true;
+    sketcher.skip_non_acgt = args.skip_non_acgt;
+    sketcher.hash_bits = args.hash_bits;
 
     let mut bottom_sketches = vec![];
     let mut bucket_sketches = vec![];
     let start = std::time::Instant::now();
 
-    for path in paths {
+    for path in &paths {
         trace!("Sketching {path:?}");
         let mut seq = AsciiSeqVec::default();
+        let mut ranges = vec![];
         let mut reader = needletail::parse_fastx_file(path).unwrap();
         let start = std::time::Instant::now();
         while let Some(r) = reader.next() {
-            // let record = r
-            //     .unwrap()
-            //     .seq();
-            // .iter()
-            // .filter_map(|&b| if b == b'N' { None } else { Some(b) })
-            // .collect::<Vec<_>>();
-            // seq.push_ascii(&record);
-            seq.push_ascii(&r.unwrap().seq());
-            // FIXME: Skip adjacent k-mers.
+            ranges.push(seq.push_ascii(&r.unwrap().seq()));
         }
+        let records = ranges.into_iter().map(|r| seq.slice(r)).collect_vec();
         trace!("Reading & filtering took {:?}", start.elapsed());
         let start = std::time::Instant::now();
         if args.bucket {
-            bucket_sketches.push(sketcher.sketch(seq.as_slice()));
+            bucket_sketches.push(sketcher.sketch_seqs(&records));
         } else {
-            bottom_sketches.push(sketcher.bottom_sketch(seq.as_slice()));
+            bottom_sketches.push(sketcher.bottom_sketch_seqs(&records));
         };
         trace!("sketching itself took {:?}", start.elapsed());
     }
@@ -77,18 +84,31 @@ fn main() {
     );
 
     let start = std::time::Instant::now();
-    let dists = if args.bucket {
-        bucket_sketches
+    let dists = match (args.bucket, args.mash) {
+        (true, false) => bucket_sketches
+            .iter()
+            .tuple_combinations()
+            .map(|(s1, s2)| s1.similarity(s2).to_string())
+            .collect_vec(),
+        (true, true) => bucket_sketches
+            .iter()
+            .tuple_combinations()
+            .map(|(s1, s2)| s1.distance(s2))
+            .zip(paths.iter().tuple_combinations())
+            .map(mash_line)
+            .collect_vec(),
+        (false, false) => bottom_sketches
             .iter()
             .tuple_combinations()
-            .map(|(s1, s2)| s1.similarity(s2))
-            .collect_vec()
-    } else {
-        bottom_sketches
+            .map(|(s1, s2)| s1.similarity(s2).to_string())
+            .collect_vec(),
+        (false, true) => bottom_sketches
             .iter()
             .tuple_combinations()
-            .map(|(s1, s2)| s1.similarity(s2))
-            .collect_vec()
+            .map(|(s1, s2)| s1.distance(s2))
+            .zip(paths.iter().tuple_combinations())
+            .map(mash_line)
+            .collect_vec(),
     };
     let t_dist = start.elapsed();
     let cnt = q * (q - 1) / 2;
@@ -108,7 +128,6 @@ fn main() {
         let mut writer = std::fs::File::options()
             .create(true)
             .append(true)
-            .write(true)
             .open(stats)
             .unwrap();
         writeln!(
@@ -126,6 +145,19 @@ fn main() {
     }
 }
 
+fn mash_line((d, (p1, p2)): (simd_sketch::Distance, (&PathBuf, &PathBuf))) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}/{}",
+        p1.display(),
+        p2.display(),
+        d.mash_distance,
+        d.ani,
+        d.p_value,
+        d.shared,
+        d.total
+    )
+}
+
 fn init_trace() {
     use tracing::level_filters::LevelFilter;
     use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -0,0 +1,441 @@
+//! Incrementally sketching sequences that are pushed one at a time.
+
+use packed_seq::Seq;
+
+use crate::{
+    AnySketch, KmerHasher, NtHash, Sketcher,
+    hash::{FastMod, HashValue},
+    kmer_chunks, max_hash_for_scale, mix64,
+};
+
+/// Pushed sequences are hashed in windows of this many k-mers, to bound the number of buffered hashes.
+const WINDOW: usize = 1 << 20;
+
+/// Candidate hashes are only compacted once there are at least this many.
+const MIN_BUFFER: usize = 1 << 12;
+
+/// The number of rows of the [`CountMin`] sketch.
+const COUNT_MIN_ROWS: usize = 4;
+
+/// The number of counters in each row of the [`CountMin`] sketch, for 8 MiB in total.
+const COUNT_MIN_WIDTH: usize = 1 << 20;
+
+/// A count-min sketch, counting hashes approximately in fixed memory.
+/// Estimates never underestimate the true count, and saturate at `u16::MAX`.
+struct CountMin {
+    /// `COUNT_MIN_ROWS` rows of `COUNT_MIN_WIDTH` counters.
+    counters: Vec<u16>,
+}
+
+impl CountMin {
+    fn new() -> Self {
+        CountMin {
+            counters: vec![0; COUNT_MIN_ROWS * COUNT_MIN_WIDTH],
+        }
+    }
+
+    /// Count one more occurrence of `hash` and return its estimated count.
+    fn insert(&mut self, hash: u64) -> u64 {
+        let mut min = u16::MAX;
+        for (row, counters) in self.counters.chunks_exact_mut(COUNT_MIN_WIDTH).enumerate() {
+            let i = mix64(hash ^ (row as u64).wrapping_mul(0x9e3779b97f4a7c15)) as usize;
+            let c = &mut counters[i % COUNT_MIN_WIDTH];
+            *c = c.saturating_add(1);
+            min = min.min(*c);
+        }
+        min as u64
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Kind {
+    Bottom,
+    Bucket,
+    Scaled(u64),
+}
+
+/// Incrementally builds a sketch of sequences that are pushed one at a time,
+/// such as the reads of a FASTQ stream that does not fit in memory.
+///
+/// Created by [`Sketcher::bottom_sketch_builder`], [`Sketcher::sketch_builder`], and [`Sketcher::scaled_sketch_builder`].
+/// [`SketchBuilder::finish`] returns the same sketch as sketching all pushed sequences at once
+/// using [`Sketcher::bottom_sketch_seqs`], [`Sketcher::sketch_seqs`], or [`Sketcher::scaled_sketch_seqs`].
+///
+/// A bottom sketch keeps at most `2s` candidate hashes below a shrinking threshold:
+/// the largest of the `s` smallest distinct hashes so far.
+/// A bucket sketch keeps the smallest hash of each bucket, and only collects hashes
+/// below the largest bucket minimum.
+/// A scaled sketch keeps all distinct hashes up to [`ScaledSketch::max_hash`](crate::ScaledSketch::max_hash).
+///
+/// When [`Sketcher::min_count`] is larger than 1 or [`Sketcher::track_abundance`] is set,
+/// candidate hashes are kept together with their counts,
+/// and only hashes that occurred at least `min_count` times lower the threshold.
+/// For `min_count > 1`, occurrences are first counted in a count-min sketch of fixed size,
+/// and a hash only becomes a candidate once its estimated count reaches `min_count`,
+/// so that sequencing errors in high-coverage reads do not fill the candidates.
+/// Since estimates can exceed the true count, a few rarer k-mers may be kept as well,
+/// depending on the order in which sequences are pushed.
+pub struct SketchBuilder<'a, H: KmerHasher = NtHash> {
+    sketcher: &'a Sketcher<H>,
+    kind: Kind,
+    /// Candidate hashes, or for bucket sketches the smallest hash of each bucket (`T::MAX` when empty).
+    hashes: Vec<u64>,
+    /// Candidate hashes and their counts, when hashes are counted.
+    /// With a count-min sketch, only occurrences since the estimate reached the threshold are counted.
+    counts: Vec<(u64, u64)>,
+    /// Approximate counts of all hashes below the bound, when `min_count > 1`.
+    count_min: Option<CountMin>,
+    /// Hashes larger than this can not end up in the sketch anymore.
+    bound: u64,
+    /// The number of candidates after the last compaction,
+    /// or for bucket sketches without counts, the number of bucket updates since `bound` was last lowered.
+    count: usize,
+    seq_len: u64,
+    /// The number of k-mers hashed at once, [`WINDOW`] by default.
+    window: usize,
+}
+
+impl<H: KmerHasher> Sketcher<H> {
+    /// Incrementally build a bottom sketch, as returned by [`Sketcher::bottom_sketch_seqs`].
+    pub fn bottom_sketch_builder(&self) -> SketchBuilder<'_, H> {
+        SketchBuilder::new(self, Kind::Bottom)
+    }
+
+    /// Incrementally build a bucket sketch, as returned by [`Sketcher::sketch_seqs`].
+    pub fn sketch_builder(&self) -> SketchBuilder<'_, H> {
+        SketchBuilder::new(self, Kind::Bucket)
+    }
+
+    /// Incrementally build a FracMinHash sketch, as returned by [`Sketcher::scaled_sketch_seqs`].
+    pub fn scaled_sketch_builder(&self, scale: u64) -> SketchBuilder<'_, H> {
+        assert!(scale > 0, "Scale must be positive.");
+        SketchBuilder::new(self, Kind::Scaled(scale))
+    }
+
+    /// Push all `seqs` into `builder` and return the sketch.
+    pub(crate) fn build<'s, S: Seq<'s> + Send + Sync>(
+        &self,
+        mut builder: SketchBuilder<'_, H>,
+        seqs: &[S],
+    ) -> AnySketch {
+        for &seq in seqs {
+            builder.push(seq);
+        }
+        builder.finish()
+    }
+}
+
+impl<'a, H: KmerHasher> SketchBuilder<'a, H> {
+    fn new(sketcher: &'a Sketcher<H>, kind: Kind) -> Self {
+        assert!(
+            matches!(sketcher.hash_bits, 32 | 64),
+            "Unsupported hash width. Must be 32 or 64."
+        );
+        let max = u64::MAX >> (64 - sketcher.hash_bits);
+        let (hashes, bound) = match kind {
+            Kind::Bottom => (vec![], max),
+            Kind::Bucket => (vec![max; sketcher.s], max),
+            Kind::Scaled(scale) => (vec![], max_hash_for_scale(sketcher.hash_bits, scale)),
+        };
+        SketchBuilder {
+            sketcher,
+            kind,
+            hashes,
+            counts: vec![],
+            count_min: (sketcher.min_count > 1).then(CountMin::new),
+            bound,
+            count: 0,
+            seq_len: 0,
+            window: WINDOW,
+        }
+    }
+
+    /// Add the k-mers of a sequence, such as a read or a contig.
+    ///
+    /// As for [`Sketcher::sketch_seqs`], k-mers spanning two pushed sequences are not hashed.
+    /// To push a long sequence in parts, let consecutive parts overlap by `k-1` characters.
+    pub fn push<'s, S: Seq<'s> + Send + Sync>(&mut self, seq: S) {
+        match self.sketcher.hash_bits {
+            32 => self.push_generic::<u32, S>(seq),
+            64 => self.push_generic::<u64, S>(seq),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The total length of the pushed sequences.
+    pub fn seq_len(&self) -> u64 {
+        self.seq_len
+    }
+
+    /// Whether hashes are counted, to filter them by [`Sketcher::min_count`] or to track their abundance.
+    fn counted(&self) -> bool {
+        self.sketcher.min_count > 1
+            || (self.sketcher.track_abundance && !matches!(self.kind, Kind::Bucket))
+    }
+
+    /// The estimated count at which a hash becomes a candidate.
+    /// Unless it is overestimated, a hash reaches it at its `threshold`th occurrence,
+    /// so its count is the number of candidate occurrences plus `threshold - 1`.
+    fn threshold(&self) -> u64 {
+        (self.sketcher.min_count as u64).clamp(1, u16::MAX as u64)
+    }
+
+    fn push_generic<'s, T: HashValue, S: Seq<'s> + Send + Sync>(&mut self, seq: S) {
+        self.seq_len += seq.len() as u64;
+        let k = self.sketcher.k;
+        if seq.len() < k {
+            return;
+        }
+        let mut out = vec![];
+        for range in kmer_chunks(seq.len(), k, self.window) {
+            self.sketcher.collect_up_to_bound(
+                &[seq.slice(range)],
+                T::from_u64(self.bound),
+                &mut out,
+            );
+            // 64-bit collection only filters on the high half, so filter again exactly.
+            let bound = self.bound;
+            let below_bound = |h: &u64| *h <= bound;
+            if self.counted() {
+                let threshold = self.threshold();
+                for h in out.iter().map(|h| h.to_u64()).filter(below_bound) {
+                    if self
+                        .count_min
+                        .as_mut()
+                        .is_none_or(|count_min| count_min.insert(h) >= threshold)
+                    {
+                        self.counts.push((h, 1));
+                    }
+                }
+                if self.counts.len() >= (2 * self.count).max(2 * self.sketcher.s).max(MIN_BUFFER) {
+                    self.compact_counts::<T>();
+                }
+                continue;
+            }
+            match self.kind {
+                Kind::Bottom | Kind::Scaled(_) => {
+                    self.hashes
+                        .extend(out.iter().map(|h| h.to_u64()).filter(below_bound));
+                    if self.hashes.len()
+                        >= (2 * self.count).max(2 * self.sketcher.s).max(MIN_BUFFER)
+                    {
+                        self.compact();
+                    }
+                }
+                Kind::Bucket => {
+                    let m = T::FastMod::new(self.sketcher.s);
+                    for &hash in &out {
+                        let bucket = &mut self.hashes[m.fastmod(hash)];
+                        if hash.to_u64() < *bucket {
+                            *bucket = hash.to_u64();
+                            self.count += 1;
+                        }
+                    }
+                    // Lowering the bound takes `O(s)`, so only do it once per `s` updates.
+                    if self.count >= self.sketcher.s {
+                        self.bound = self.hashes.iter().copied().max().unwrap_or(self.bound);
+                        self.count = 0;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sort and deduplicate the candidate hashes.
+    /// For bottom sketches, keep only the `s` smallest and lower the bound to the largest of them.
+    fn compact(&mut self) {
+        self.hashes.sort_unstable();
+        self.hashes.dedup();
+        if let Kind::Bottom = self.kind {
+            let s = self.sketcher.s;
+            self.hashes.truncate(s);
+            if self.hashes.len() == s
+                && let Some(&max) = self.hashes.last()
+            {
+                self.bound = max;
+            }
+        }
+        self.count = self.hashes.len();
+    }
+
+    /// Sort the counted candidate hashes and sum the counts of equal hashes.
+    /// Then lower the bound using the hashes that occurred at least `min_count` times,
+    /// and drop the candidates that can not end up in the sketch anymore.
+    fn compact_counts<T: HashValue>(&mut self) {
+        let min_count = self.sketcher.min_count as u64;
+        let offset = self.threshold() - 1;
+        self.counts.sort_unstable_by_key(|&(h, _)| h);
+        self.counts.dedup_by(|(h, c), (prev_h, prev_c)| {
+            let equal = h == prev_h;
+            if equal {
+                *prev_c = prev_c.saturating_add(*c);
+            }
+            equal
+        });
+        let solid = |&&(_, c): &&(u64, u64)| c.saturating_add(offset) >= min_count;
+        match self.kind {
+            Kind::Bottom => {
+                let s = self.sketcher.s;
+                if let Some(&(max, _)) = s
+                    .checked_sub(1)
+                    .and_then(|i| self.counts.iter().filter(solid).nth(i))
+                {
+                    self.bound = max;
+                    self.counts
+                        .truncate(self.counts.partition_point(|&(h, _)| h <= max));
+                }
+            }
+            Kind::Bucket => {
+                let m = T::FastMod::new(self.sketcher.s);
+                let bucket = |h: u64| m.fastmod(T::from_u64(h));
+                for &(h, _) in self.counts.iter().filter(solid) {
+                    let min = &mut self.hashes[bucket(h)];
+                    *min = (*min).min(h);
+                }
+                // Only hashes below the current minimum of their bucket can still change it.
+                let buckets = &self.hashes;
+                self.counts.retain(|&(h, _)| h < buckets[bucket(h)]);
+                self.bound = self.hashes.iter().copied().max().unwrap_or(self.bound);
+            }
+            Kind::Scaled(_) => {}
+        }
+        self.count = self.counts.len();
+    }
+
+    /// Return the sketch of all pushed sequences.
+    pub fn finish(self) -> AnySketch {
+        match self.sketcher.hash_bits {
+            32 => self.finish_generic::<u32>(),
+            64 => self.finish_generic::<u64>(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn finish_generic<T: HashValue>(mut self) -> AnySketch {
+        let (hashes, counts) = match self.kind {
+            Kind::Bucket => {
+                if self.counted() {
+                    self.compact_counts::<T>();
+                }
+                (self.hashes, vec![])
+            }
+            _ if self.counted() => {
+                self.compact_counts::<T>();
+                let min_count = self.sketcher.min_count as u64;
+                let offset = self.threshold() - 1;
+                self.counts
+                    .into_iter()
+                    .map(|(h, c)| (h, c.saturating_add(offset)))
+                    .filter(|&(_, c)| c >= min_count)
+                    .unzip()
+            }
+            _ => {
+                self.compact();
+                (self.hashes, vec![])
+            }
+        };
+        let hashes = hashes.into_iter().map(T::from_u64).collect::<Vec<_>>();
+        let sketcher = self.sketcher;
+        let abundances = sketcher.track_abundance.then_some(counts);
+        match self.kind {
+            Kind::Bottom => sketcher
+                .bottom_sketch_from(hashes, abundances, self.seq_len)
+                .into(),
+            Kind::Bucket => sketcher.bucket_sketch_from(hashes, self.seq_len).into(),
+            Kind::Scaled(scale) => sketcher
+                .scaled_sketch_from(hashes, abundances, scale, self.seq_len)
+                .into(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn streaming() {
+    use packed_seq::{AsciiSeqVec, SeqVec};
+
+    // Reads with sequencing errors sampled from a genome, so that some k-mers occur many times.
+    let genome = AsciiSeqVec::random(50000);
+    let mut seqs = AsciiSeqVec::default();
+    let mut ranges = (0..3000)
+        .map(|_| {
+            let len = rand::random_range(0..300);
+            let start = rand::random_range(0..=genome.len() - len);
+            let mut read = genome.seq[start..start + len].to_vec();
+            for c in &mut read {
+                if rand::random_range(0..100) == 0 {
+                    *c = b"ACGT"[rand::random_range(0..4)];
+                }
+            }
+            seqs.push_ascii(&read)
+        })
+        .collect::<Vec<_>>();
+    // A sequence spanning multiple windows.
+    let window = 1 << 12;
+    ranges.push(seqs.push_ascii(&AsciiSeqVec::random(3 * window + 1000).seq));
+    let reads = ranges
+        .into_iter()
+        .map(|r| seqs.slice(r))
+        .collect::<Vec<_>>();
+
+    for (s, hash_bits, min_count, track_abundance) in [
+        (64, 32, 1, false),
+        (1024, 32, 3, false),
+        (1024, 64, 1, false),
+        (1024, 64, 3, false),
+        (1 << 16, 32, 2, false),
+        (1024, 64, 1, true),
+        (1 << 16, 32, 2, true),
+    ] {
+        let mut sketcher = Sketcher::new_rc(21, s, 32);
+        sketcher.hash_bits = hash_bits;
+        sketcher.min_count = min_count;
+        if track_abundance {
+            sketcher.b = hash_bits;
+            sketcher.track_abundance = true;
+        }
+        sketcher.filter_empty = true;
+        for (mut builder, expected) in [
+            (
+                sketcher.bottom_sketch_builder(),
+                AnySketch::from(sketcher.bottom_sketch_seqs(&reads)),
+            ),
+            (
+                sketcher.sketch_builder(),
+                sketcher.sketch_seqs(&reads).into(),
+            ),
+            (
+                sketcher.scaled_sketch_builder(1000),
+                sketcher.scaled_sketch_seqs(&reads, 1000).into(),
+            ),
+        ] {
+            // With `min_count > 1`, the sketch may depend on the order of the k-mers, which depends on the window.
+            if min_count == 1 {
+                builder.window = window;
+            }
+            for &read in &reads {
+                builder.push(read);
+            }
+            assert_eq!(
+                builder.finish(),
+                expected,
+                "s={s} hash_bits={hash_bits} min_count={min_count} track_abundance={track_abundance}"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn count_min() {
+    let mut count_min = CountMin::new();
+    for i in 0..1000u64 {
+        for j in 0..i % 5 {
+            assert_eq!(count_min.insert(mix64(i)), j + 1);
+        }
+    }
+    for _ in 0..u16::MAX {
+        count_min.insert(0);
+    }
+    assert_eq!(count_min.insert(0), u16::MAX as u64);
+}
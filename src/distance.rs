@@ -0,0 +1,157 @@
+//! Mash distance, ANI, and Mash p-values derived from Jaccard similarities.
+//!
+//! See [Ondov et al., 2016](https://doi.org/10.1186/s13059-016-0997-x) for the definitions.
+
+/// Mash-style distance statistics between two sketched inputs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Distance {
+    /// The (b-bit corrected) Jaccard similarity.
+    pub jaccard: f32,
+    /// The Mash distance, `-1/k * ln(2J / (1+J))`, capped at 1.
+    pub mash_distance: f32,
+    /// The ANI estimate, `1 - mash_distance`.
+    pub ani: f32,
+    /// The probability of observing at least `shared` of the `total` hashes by chance
+    /// for random inputs of the same sequence lengths.
+    pub p_value: f64,
+    /// The (estimated) number of shared hashes.
+    pub shared: usize,
+    /// The number of hashes the estimate is based on.
+    pub total: usize,
+}
+
+impl Distance {
+    /// Compute all statistics for a Jaccard similarity estimated from `total` hashes,
+    /// for k-mers of length `k` and inputs with sequence lengths `len_a` and `len_b`.
+    pub fn new(jaccard: f32, total: usize, k: usize, len_a: u64, len_b: u64) -> Self {
+        let jaccard = jaccard.clamp(0.0, 1.0);
+        let shared = (jaccard as f64 * total as f64).round() as usize;
+        let mash_distance = mash_distance(jaccard, k);
+        Distance {
+            jaccard,
+            mash_distance,
+            ani: 1.0 - mash_distance,
+            p_value: mash_p_value(shared, total, k, len_a, len_b),
+            shared,
+            total,
+        }
+    }
+}
+
+/// The Mash distance `-1/k * ln(2J / (1+J))` corresponding to Jaccard similarity `jaccard`, capped at 1.
+pub fn mash_distance(jaccard: f32, k: usize) -> f32 {
+    if jaccard <= 0.0 {
+        return 1.0;
+    }
+    let jaccard = jaccard.min(1.0) as f64;
+    let d = ((1.0 + jaccard) / (2.0 * jaccard)).ln() / k as f64;
+    d.clamp(0.0, 1.0) as f32
+}
+
+/// The ANI estimate `1 - mash_distance` corresponding to Jaccard similarity `jaccard`.
+pub fn ani(jaccard: f32, k: usize) -> f32 {
+    1.0 - mash_distance(jaccard, k)
+}
+
+/// The Mash p-value: the probability that two random inputs with sequence lengths `len_a` and `len_b`
+/// share at least `shared` of `total` sketched hashes, as computed by `mash dist`.
+///
+/// Following Mash, a random k-mer occurs in an input of length `l` with probability `r = 1 / (1 + 4^k / l)`,
+/// so that the expected Jaccard similarity of random inputs is `p = r_a r_b / (r_a + r_b - r_a r_b)`.
+/// The number of shared hashes then follows a binomial distribution `B(total, p)`,
+/// where `total` is the number of hashes of the union of both sketches that were considered.
+pub fn mash_p_value(shared: usize, total: usize, k: usize, len_a: u64, len_b: u64) -> f64 {
+    if shared == 0 {
+        return 1.0;
+    }
+    let kmer_space = 4f64.powi(k as i32);
+    let r = |len: u64| 1.0 / (1.0 + kmer_space / len as f64);
+    let (ra, rb) = (r(len_a), r(len_b));
+    if ra == 0.0 || rb == 0.0 {
+        return 0.0;
+    }
+    let p = ra * rb / (ra + rb - ra * rb);
+    binomial_upper_tail(total, p, shared)
+}
+
+/// `P(X >= x)` for `X ~ B(n, p)`, summed in log-space to avoid underflow.
+fn binomial_upper_tail(n: usize, p: f64, x: usize) -> f64 {
+    if x > n {
+        return 0.0;
+    }
+    if p >= 1.0 {
+        return 1.0;
+    }
+    if p <= 0.0 {
+        return 0.0;
+    }
+    let (ln_p, ln_q) = (p.ln(), (-p).ln_1p());
+    // ln of the probability mass at `x`.
+    let ln_choose = (1..=x)
+        .map(|j| ((n - x + j) as f64 / j as f64).ln())
+        .sum::<f64>();
+    let mut ln_pmf = ln_choose + x as f64 * ln_p + (n - x) as f64 * ln_q;
+    let mut max = ln_pmf;
+    let mut sum = 1.0;
+    for i in x..n {
+        ln_pmf += ((n - i) as f64 / (i + 1) as f64).ln() + ln_p - ln_q;
+        if ln_pmf > max {
+            sum = sum * (max - ln_pmf).exp() + 1.0;
+            max = ln_pmf;
+        } else {
+            sum += (ln_pmf - max).exp();
+        }
+    }
+    (max + sum.ln()).exp().min(1.0)
+}
+
+#[cfg(test)]
+#[test]
+fn distances() {
+    assert_eq!(mash_distance(1.0, 21), 0.0);
+    assert_eq!(mash_distance(0.0, 21), 1.0);
+    // 2J/(1+J) = 1/e for J = 1/(2e - 1).
+    let j = 1.0 / (2.0 * std::f32::consts::E - 1.0);
+    assert!((mash_distance(j, 21) - 1.0 / 21.0).abs() < 1e-6);
+    assert!((ani(j, 21) - 20.0 / 21.0).abs() < 1e-6);
+
+    // Exact binomial tails for small n.
+    let tail = |n: usize, p: f64, x: usize| {
+        (x..=n)
+            .map(|i| {
+                let choose = (1..=i)
+                    .map(|j| (n - i + j) as f64 / j as f64)
+                    .product::<f64>();
+                choose * p.powi(i as i32) * (1.0 - p).powi((n - i) as i32)
+            })
+            .sum::<f64>()
+    };
+    for n in [1, 10, 50] {
+        for p in [0.01, 0.3, 0.9] {
+            for x in 0..=n {
+                let (a, b) = (binomial_upper_tail(n, p, x), tail(n, p, x));
+                assert!(
+                    (a - b).abs() <= 1e-9 * b.max(1e-300),
+                    "{n} {p} {x}: {a} {b}"
+                );
+            }
+        }
+    }
+
+    // Sharing many hashes between large random genomes is very unlikely for k=21, but not for k=8.
+    let len = 5_000_000;
+    assert!(mash_p_value(100, 1000, 21, len, len) < 1e-100);
+    assert!(mash_p_value(100, 1000, 8, len, len) > 0.99);
+    assert_eq!(mash_p_value(0, 1000, 21, len, len), 1.0);
+
+    // A single shared hash has probability `1 - (1 - p)^total`, with `p` as in Mash.
+    let r = 1.0 / (1.0 + 4f64.powi(21) / len as f64);
+    let p = r * r / (2.0 * r - r * r);
+    let expected = 1.0 - (1.0 - p).powi(1000);
+    let actual = mash_p_value(1, 1000, 21, len, len);
+    assert!(
+        (actual - expected).abs() < 1e-9 * expected,
+        "{actual} {expected}"
+    );
+    assert!((actual - 5.6828e-4).abs() < 1e-7, "{actual}");
+}
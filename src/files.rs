@@ -0,0 +1,194 @@
+//! Sketching FASTA and FASTQ files, optionally compressed, in parallel.
+
+use std::{io, path::Path};
+
+use packed_seq::{AsciiSeq, AsciiSeqVec, SeqVec};
+
+use crate::{BottomSketch, BucketSketch, KmerHasher, Metadata, ScaledSketch, Sketcher};
+
+impl Metadata {
+    /// The metadata of the given records read from the file at `path`:
+    /// the path, the number of records, and their number of `G` and `C` characters.
+    pub fn from_records(path: &Path, records: &[AsciiSeq]) -> Self {
+        let gc = records
+            .iter()
+            .map(|r| {
+                r.0.iter()
+                    .filter(|&&c| matches!(c, b'G' | b'C' | b'g' | b'c'))
+                    .count() as u64
+            })
+            .sum();
+        Metadata {
+            path: path.display().to_string(),
+            records: Some(records.len() as u64),
+            gc: Some(gc),
+        }
+    }
+}
+
+/// Read all records of a FASTA/FASTQ file (optionally compressed), and pass their ids and sequences to `f`.
+///
+/// The id of a record is the first word of its header.
+/// Use [`Metadata::from_records`] to get the metadata of (some of) the records.
+pub fn with_fastx_records<T>(
+    path: &Path,
+    f: impl FnOnce(&[String], &[AsciiSeq]) -> T,
+) -> io::Result<T> {
+    let error = |e: needletail::errors::ParseError| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    };
+    let mut reader = needletail::parse_fastx_file(path).map_err(error)?;
+    let mut seq = AsciiSeqVec::default();
+    let mut ranges = vec![];
+    let mut ids = vec![];
+    while let Some(record) = reader.next() {
+        let record = record.map_err(error)?;
+        ranges.push(seq.push_ascii(&record.seq()));
+        let id = String::from_utf8_lossy(record.id());
+        ids.push(id.split_whitespace().next().unwrap_or_default().to_string());
+    }
+    let records = ranges.into_iter().map(|r| seq.slice(r)).collect::<Vec<_>>();
+    Ok(f(&ids, &records))
+}
+
+impl<H: KmerHasher> Sketcher<H> {
+    /// Bottom-sketch each FASTA/FASTQ file (optionally compressed) in parallel, using [`Sketcher::sketch_many`].
+    ///
+    /// All records of a file are sketched together, as by [`Sketcher::bottom_sketch_seqs`].
+    /// The sketch is named after the path, and its [`Metadata`] is set. Results are in the order of `paths`.
+    pub fn bottom_sketch_files<P: AsRef<Path> + Sync>(
+        &self,
+        paths: &[P],
+    ) -> Vec<io::Result<BottomSketch>> {
+        self.sketch_many(paths, |sketcher, path| {
+            let path = path.as_ref();
+            let (mut sketch, metadata) = with_fastx_records(path, |_, seqs| {
+                (
+                    sketcher.bottom_sketch_seqs(seqs),
+                    Metadata::from_records(path, seqs),
+                )
+            })?;
+            sketch.name = path.display().to_string();
+            sketch.metadata = metadata;
+            Ok(sketch)
+        })
+    }
+
+    /// Bucket-sketch each FASTA/FASTQ file (optionally compressed) in parallel,
+    /// as [`Sketcher::bottom_sketch_files`] does for bottom sketches.
+    pub fn sketch_files<P: AsRef<Path> + Sync>(
+        &self,
+        paths: &[P],
+    ) -> Vec<io::Result<BucketSketch>> {
+        self.sketch_many(paths, |sketcher, path| {
+            let path = path.as_ref();
+            let (mut sketch, metadata) = with_fastx_records(path, |_, seqs| {
+                (
+                    sketcher.sketch_seqs(seqs),
+                    Metadata::from_records(path, seqs),
+                )
+            })?;
+            sketch.name = path.display().to_string();
+            sketch.metadata = metadata;
+            Ok(sketch)
+        })
+    }
+
+    /// FracMinHash-sketch each FASTA/FASTQ file (optionally compressed) in parallel,
+    /// as [`Sketcher::bottom_sketch_files`] does for bottom sketches.
+    pub fn scaled_sketch_files<P: AsRef<Path> + Sync>(
+        &self,
+        paths: &[P],
+        scale: u64,
+    ) -> Vec<io::Result<ScaledSketch>> {
+        self.sketch_many(paths, |sketcher, path| {
+            let path = path.as_ref();
+            let (mut sketch, metadata) = with_fastx_records(path, |_, seqs| {
+                (
+                    sketcher.scaled_sketch_seqs(seqs, scale),
+                    Metadata::from_records(path, seqs),
+                )
+            })?;
+            sketch.name = path.display().to_string();
+            sketch.metadata = metadata;
+            Ok(sketch)
+        })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn sketch_files() {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+    let dir = std::env::temp_dir().join(format!("simd-sketch-files-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let seqs = (0..6)
+        .map(|_| AsciiSeqVec::random(3000 + 1000 * rand::random_range(0..5)))
+        .collect::<Vec<_>>();
+    let mut paths = vec![];
+    for (i, seq) in seqs.iter().enumerate() {
+        let (a, b) = seq.seq.split_at(1000);
+        let fasta = [b">a\n", a, b"\n>b\n", b, b"\n"].concat();
+        let path = dir.join(format!("{i}.fa.gz"));
+        let mut w = flate2::write::GzEncoder::new(
+            std::fs::File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        w.write_all(&fasta).unwrap();
+        w.finish().unwrap();
+        paths.push(path);
+    }
+    paths.insert(3, dir.join("missing.fa"));
+
+    let mut sketcher = Sketcher::new_rc(21, 256, 32);
+    sketcher.max_in_flight = 2;
+    let sketches = sketcher.bottom_sketch_files(&paths);
+    assert_eq!(sketches.len(), 7);
+    assert!(sketches[3].is_err());
+    for (sketch, seq) in std::iter::zip(sketches.iter().filter_map(|s| s.as_ref().ok()), &seqs) {
+        let records = [seq.slice(0..1000), seq.slice(1000..seq.len())];
+        assert_eq!(
+            sketch.similarity(&sketcher.bottom_sketch_seqs(&records)),
+            1.0
+        );
+        assert_eq!(sketch.seq_len(), seq.len() as u64);
+        let gc = seq.seq.iter().filter(|&&c| c == b'G' || c == b'C').count() as u64;
+        assert_eq!(sketch.metadata.records, Some(2));
+        assert_eq!(sketch.metadata.gc, Some(gc));
+    }
+    assert_eq!(
+        sketches[4].as_ref().unwrap().name,
+        paths[4].display().to_string()
+    );
+    assert_eq!(
+        sketches[4].as_ref().unwrap().metadata.path,
+        paths[4].display().to_string()
+    );
+    let ids = with_fastx_records(&paths[0], |ids, seqs| {
+        assert_eq!(seqs.len(), 2);
+        ids.to_vec()
+    })
+    .unwrap();
+    assert_eq!(ids, ["a", "b"]);
+    assert!(sketcher.sketch_files(&paths[..1])[0].is_ok());
+    assert!(sketcher.scaled_sketch_files(&paths[..1], 10)[0].is_ok());
+
+    // At most `max_in_flight` inputs are processed at once.
+    let in_flight = AtomicUsize::new(0);
+    let max = AtomicUsize::new(0);
+    let out = sketcher.sketch_many(&(0..20).collect::<Vec<_>>(), |_, &i| {
+        max.fetch_max(in_flight.fetch_add(1, SeqCst) + 1, SeqCst);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        in_flight.fetch_sub(1, SeqCst);
+        i
+    });
+    assert_eq!(out, (0..20).collect::<Vec<_>>());
+    assert!(max.load(SeqCst) <= 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,1052 @@
+//! k-mer hash functions, and 32-bit and 64-bit k-mer hash values.
+//!
+//! Hash functions implement [`KmerHasher`], which hashes all k-mers of a sequence,
+//! either using SIMD over 8 chunks of the sequence, or one k-mer at a time.
+//!
+//! 32-bit hashes are plain (canonical) ntHash values by default, or a bijective mix of the packed k-mer for [`KmerMix`].
+//! 64-bit hashes use the 32-bit hash as their high half, and a second hash with
+//! different seed values as their low half. Both halves are computed in the same SIMD pass.
+//! This way, the bound on the high half can be used to filter hashes exactly as in the 32-bit case.
+
+use std::array::from_fn;
+
+use packed_seq::{Seq, complement_base, u32x8};
+use simd_minimizers::private::nthash::{
+    CharHasher, NtHasher, nthash_mapper, nthash_seq_scalar, nthash_seq_simd,
+};
+
+use crate::intrinsics;
+
+/// Identifies the hash function (and seed) used to build a sketch.
+/// Sketches with different ids can not be compared.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HasherId {
+    /// The kind of hash function: 0 for [`NtHash`], 1 for [`KmerMix`], and 2 for [`Murmur3`].
+    /// Custom hashers should use values of 128 and up.
+    pub kind: u8,
+    /// The seed of a [`Seeded`] hasher, or 0.
+    pub seed: u64,
+}
+
+/// A hash function on k-mers, with a SIMD and a scalar implementation.
+///
+/// Both implementations must return the same multiset of hashes.
+/// 64-bit hashes must be independent of the 32-bit hashes;
+/// their high halves are used to filter hashes before the low halves are combined.
+pub trait KmerHasher: Clone + std::fmt::Debug + Send + Sync {
+    /// The id recorded in sketches built using this hasher.
+    fn id(&self) -> HasherId;
+
+    /// The 32-bit hashes of all k-mers in `seq`, for canonical k-mers when `RC` is true.
+    /// Returns an iterator over the hashes of 8 chunks of the sequence in parallel,
+    /// and an iterator over the hashes of the remaining k-mers.
+    fn hash32_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (impl Iterator<Item = u32x8>, impl Iterator<Item = u32>);
+
+    /// The 32-bit hashes of all k-mers in `seq`, one at a time.
+    fn hash32_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u32>;
+
+    /// The high and low halves of the 64-bit hashes of all k-mers in `seq`,
+    /// for 8 chunks in parallel and the remaining k-mers.
+    fn hash64_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (
+        impl Iterator<Item = (u32x8, u32x8)>,
+        impl Iterator<Item = (u32, u32)>,
+    );
+
+    /// The 64-bit hashes of all k-mers in `seq`, one at a time.
+    fn hash64_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u64>;
+}
+
+/// (Canonical) ntHash. This has some hash collisions for `k <= 16`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NtHash;
+
+/// A bijective mix of the 2-bit packed k-mer, or for canonical hashes, of the smallest of
+/// the packed k-mer and its reverse complement.
+/// This is injective on (canonical) k-mers, but requires `k <= 16`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct KmerMix;
+
+/// MurmurHash3 with seed 42 of the (canonical) k-mer string, as used by Mash and sourmash.
+/// 32-bit hashes use the x86 32-bit variant, and 64-bit hashes the first word of the x64 128-bit variant.
+/// This is computed without SIMD, and mostly useful to exchange sketches with Mash.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Murmur3;
+
+/// A hasher whose hashes are remixed using a seed, giving a different random permutation of k-mers for each seed.
+/// Sketches with different seeds are independent, and can be used as replicates.
+/// Seed 0 is the unseeded `hasher`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Seeded<H> {
+    pub hasher: H,
+    pub seed: u64,
+}
+
+impl<H> Seeded<H> {
+    pub fn new(hasher: H, seed: u64) -> Self {
+        Seeded { hasher, seed }
+    }
+
+    /// The values xor'ed into the high and low halves of hashes before remixing.
+    fn keys(&self) -> (u32, u32) {
+        let key = crate::mix64(self.seed);
+        ((key >> 32) as u32, key as u32)
+    }
+}
+
+/// Remix `h` with `key`, unless `seed` is 0.
+fn remix(seed: u64, key: u32, h: u32) -> u32 {
+    if seed == 0 { h } else { fmix32(h ^ key) }
+}
+
+/// [`remix`] on 8 lanes.
+fn remix_simd(seed: u64, key: u32, h: u32x8) -> u32x8 {
+    if seed == 0 {
+        h
+    } else {
+        fmix32_simd(h ^ u32x8::splat(key))
+    }
+}
+
+impl KmerHasher for NtHash {
+    fn id(&self) -> HasherId {
+        HasherId { kind: 0, seed: 0 }
+    }
+
+    fn hash32_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (impl Iterator<Item = u32x8>, impl Iterator<Item = u32>) {
+        nthash_seq_simd::<RC, S, NtHasher>(seq, k, 1)
+    }
+
+    fn hash32_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u32> {
+        nthash_seq_scalar::<RC, NtHasher>(seq, k)
+    }
+
+    fn hash64_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (
+        impl Iterator<Item = (u32x8, u32x8)>,
+        impl Iterator<Item = (u32, u32)>,
+    ) {
+        let (add_remove, tail) = seq.par_iter_bp_delayed(k, k - 1);
+        let mut hi_mapper = nthash_mapper::<RC, S, NtHasher>(k, 1);
+        let mut lo_mapper = nthash_mapper::<RC, S, NtHasherLo>(k, 1);
+        let mut head = add_remove.map(move |x| (hi_mapper(x), lo_mapper(x)));
+        head.by_ref().take(k - 1).for_each(drop);
+        let tail = std::iter::zip(
+            nthash_seq_scalar::<RC, NtHasher>(tail, k),
+            nthash_seq_scalar::<RC, NtHasherLo>(tail, k),
+        );
+        (head, tail)
+    }
+
+    fn hash64_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u64> {
+        std::iter::zip(
+            nthash_seq_scalar::<RC, NtHasher>(seq, k),
+            nthash_seq_scalar::<RC, NtHasherLo>(seq, k),
+        )
+        .map(|(hi, lo)| ((hi as u64) << 32) | lo as u64)
+    }
+}
+
+impl KmerHasher for KmerMix {
+    fn id(&self) -> HasherId {
+        HasherId { kind: 1, seed: 0 }
+    }
+
+    fn hash32_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (impl Iterator<Item = u32x8>, impl Iterator<Item = u32>) {
+        kmer_mix_simd::<RC, S>(seq, k, 0)
+    }
+
+    fn hash32_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u32> {
+        kmer_mix_scalar::<RC, S>(seq, k, 0)
+    }
+
+    fn hash64_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (
+        impl Iterator<Item = (u32x8, u32x8)>,
+        impl Iterator<Item = (u32, u32)>,
+    ) {
+        let (head_hi, tail_hi) = kmer_mix_simd::<RC, S>(seq, k, 0);
+        let (head_lo, tail_lo) = kmer_mix_simd::<RC, S>(seq, k, KMER_MIX_LO_SEED);
+        (
+            std::iter::zip(head_hi, head_lo),
+            std::iter::zip(tail_hi, tail_lo),
+        )
+    }
+
+    fn hash64_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u64> {
+        std::iter::zip(
+            kmer_mix_scalar::<RC, S>(seq, k, 0),
+            kmer_mix_scalar::<RC, S>(seq, k, KMER_MIX_LO_SEED),
+        )
+        .map(|(hi, lo)| ((hi as u64) << 32) | lo as u64)
+    }
+}
+
+impl KmerHasher for Murmur3 {
+    fn id(&self) -> HasherId {
+        HasherId { kind: 2, seed: 0 }
+    }
+
+    /// MurmurHash3 has no SIMD implementation, so all hashes are returned by the scalar iterator.
+    fn hash32_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (impl Iterator<Item = u32x8>, impl Iterator<Item = u32>) {
+        (std::iter::empty(), self.hash32_scalar::<RC, S>(seq, k))
+    }
+
+    fn hash32_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u32> {
+        murmur3_seq::<RC, S, _>(seq, k, |kmer| murmur3_x86_32(kmer, MASH_SEED))
+    }
+
+    /// MurmurHash3 has no SIMD implementation, so all hashes are returned by the scalar iterator.
+    fn hash64_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (
+        impl Iterator<Item = (u32x8, u32x8)>,
+        impl Iterator<Item = (u32, u32)>,
+    ) {
+        let tail = self
+            .hash64_scalar::<RC, S>(seq, k)
+            .map(|h| ((h >> 32) as u32, h as u32));
+        (std::iter::empty(), tail)
+    }
+
+    fn hash64_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u64> {
+        murmur3_seq::<RC, S, _>(seq, k, |kmer| murmur3_x64_128(kmer, MASH_SEED).0)
+    }
+}
+
+impl<H: KmerHasher> KmerHasher for Seeded<H> {
+    /// The id of `hasher`, with its seed replaced by `seed`, or combined with it when `hasher` is seeded already.
+    fn id(&self) -> HasherId {
+        let id = self.hasher.id();
+        let seed = match (id.seed, self.seed) {
+            (inner, 0) => inner,
+            (0, seed) => seed,
+            (inner, seed) => crate::mix64(inner ^ crate::mix64(seed)),
+        };
+        HasherId { seed, ..id }
+    }
+
+    fn hash32_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (impl Iterator<Item = u32x8>, impl Iterator<Item = u32>) {
+        let (seed, (key, _)) = (self.seed, self.keys());
+        let (head, tail) = self.hasher.hash32_simd::<RC, S>(seq, k);
+        (
+            head.map(move |h| remix_simd(seed, key, h)),
+            tail.map(move |h| remix(seed, key, h)),
+        )
+    }
+
+    fn hash32_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u32> {
+        let (seed, (key, _)) = (self.seed, self.keys());
+        self.hasher
+            .hash32_scalar::<RC, S>(seq, k)
+            .map(move |h| remix(seed, key, h))
+    }
+
+    fn hash64_simd<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> (
+        impl Iterator<Item = (u32x8, u32x8)>,
+        impl Iterator<Item = (u32, u32)>,
+    ) {
+        let (seed, (key_hi, key_lo)) = (self.seed, self.keys());
+        let (head, tail) = self.hasher.hash64_simd::<RC, S>(seq, k);
+        (
+            head.map(move |(hi, lo)| (remix_simd(seed, key_hi, hi), remix_simd(seed, key_lo, lo))),
+            tail.map(move |(hi, lo)| (remix(seed, key_hi, hi), remix(seed, key_lo, lo))),
+        )
+    }
+
+    fn hash64_scalar<'s, const RC: bool, S: Seq<'s>>(
+        &self,
+        seq: S,
+        k: usize,
+    ) -> impl Iterator<Item = u64> {
+        let (seed, (key_hi, key_lo)) = (self.seed, self.keys());
+        self.hasher.hash64_scalar::<RC, S>(seq, k).map(move |h| {
+            let hi = remix(seed, key_hi, (h >> 32) as u32);
+            let lo = remix(seed, key_lo, h as u32);
+            ((hi as u64) << 32) | lo as u64
+        })
+    }
+}
+
+/// Sequences shorter than this many k-mers are hashed using the scalar implementation,
+/// since the SIMD implementation has a warm-up of `k-1` characters in each of 8 chunks.
+fn use_scalar(len: usize, k: usize) -> bool {
+    len < 8 * (2 * k + 16)
+}
+
+/// A `u32` or `u64` k-mer hash value.
+pub(crate) trait HashValue:
+    Copy + Ord + Eq + std::fmt::Debug + Send + Sync + 'static
+{
+    /// The number of bits of the hash.
+    const BITS: usize;
+    const MAX: Self;
+    /// Fast modulo/division by a constant, used to assign hashes to buckets.
+    type FastMod: FastMod<Self>;
+
+    fn from_u64(x: u64) -> Self;
+    fn to_u64(self) -> u64;
+
+    /// Append all hashes up to `bound` of k-mers in `seq` to `out`.
+    fn collect_up_to_bound<'s, const RC: bool, S: Seq<'s>, H: KmerHasher>(
+        hasher: &H,
+        seq: S,
+        k: usize,
+        bound: Self,
+        out: &mut Vec<Self>,
+    );
+}
+
+/// Fast modulo and division of hashes by a fixed divisor.
+pub(crate) trait FastMod<T>: Copy {
+    fn new(d: usize) -> Self;
+    /// `h % d`
+    fn fastmod(self, h: T) -> usize;
+    /// `h / d`
+    fn fastdiv(self, h: T) -> T;
+}
+
+impl HashValue for u32 {
+    const BITS: usize = 32;
+    const MAX: Self = u32::MAX;
+    type FastMod = FM32;
+
+    fn from_u64(x: u64) -> Self {
+        x as u32
+    }
+    fn to_u64(self) -> u64 {
+        self as u64
+    }
+
+    fn collect_up_to_bound<'s, const RC: bool, S: Seq<'s>, H: KmerHasher>(
+        hasher: &H,
+        seq: S,
+        k: usize,
+        bound: u32,
+        out: &mut Vec<u32>,
+    ) {
+        if use_scalar(seq.len(), k) {
+            out.extend(
+                hasher
+                    .hash32_scalar::<RC, S>(seq, k)
+                    .filter(|&h| h <= bound),
+            );
+        } else {
+            let (head, tail) = hasher.hash32_simd::<RC, S>(seq, k);
+            collect32(head, tail, bound, out);
+        }
+    }
+}
+
+/// Append all hashes up to `bound` to `out`.
+fn collect32(
+    hashes_head: impl Iterator<Item = u32x8>,
+    hashes_tail: impl Iterator<Item = u32>,
+    bound: u32,
+    out: &mut Vec<u32>,
+) {
+    let simd_bound = u32x8::splat(bound);
+
+    let mut write_idx = out.len();
+    for hashes in hashes_head {
+        let mask = !hashes.cmp_gt(simd_bound);
+        if write_idx + 8 >= out.len() {
+            out.resize(write_idx * 3 / 2 + 8, 0);
+        }
+        unsafe { intrinsics::append_from_mask(hashes, mask, out, &mut write_idx) };
+    }
+
+    out.resize(write_idx, 0);
+
+    for hash in hashes_tail {
+        if hash <= bound {
+            out.push(hash);
+        }
+    }
+}
+
+impl HashValue for u64 {
+    const BITS: usize = 64;
+    const MAX: Self = u64::MAX;
+    type FastMod = FM64;
+
+    fn from_u64(x: u64) -> Self {
+        x
+    }
+    fn to_u64(self) -> u64 {
+        self
+    }
+
+    /// Collects all hashes whose high 32 bits are at most the high 32 bits of `bound`.
+    fn collect_up_to_bound<'s, const RC: bool, S: Seq<'s>, H: KmerHasher>(
+        hasher: &H,
+        seq: S,
+        k: usize,
+        bound: u64,
+        out: &mut Vec<u64>,
+    ) {
+        if use_scalar(seq.len(), k) {
+            out.extend(
+                hasher
+                    .hash64_scalar::<RC, S>(seq, k)
+                    .filter(|&h| h >> 32 <= bound >> 32),
+            );
+        } else {
+            let (head, tail) = hasher.hash64_simd::<RC, S>(seq, k);
+            collect64(head, tail, bound, out);
+        }
+    }
+}
+
+/// Append all `(hi, lo)` hashes whose `hi` half is at most the high half of `bound` to `out`.
+fn collect64(
+    hashes_head: impl Iterator<Item = (u32x8, u32x8)>,
+    hashes_tail: impl Iterator<Item = (u32, u32)>,
+    bound: u64,
+    out: &mut Vec<u64>,
+) {
+    let bound_hi = (bound >> 32) as u32;
+    let simd_bound = u32x8::splat(bound_hi);
+
+    let mut his: Vec<u32> = vec![];
+    let mut los: Vec<u32> = vec![];
+    let mut write_idx = 0;
+    for (hi, lo) in hashes_head {
+        let mask = !hi.cmp_gt(simd_bound);
+        if write_idx + 8 >= his.len() {
+            his.resize(write_idx * 3 / 2 + 8, 0);
+            los.resize(write_idx * 3 / 2 + 8, 0);
+        }
+        let mut lo_write_idx = write_idx;
+        unsafe { intrinsics::append_from_mask(hi, mask, &mut his, &mut write_idx) };
+        unsafe { intrinsics::append_from_mask(lo, mask, &mut los, &mut lo_write_idx) };
+    }
+
+    out.extend(
+        std::iter::zip(&his[..write_idx], &los[..write_idx])
+            .map(|(&hi, &lo)| ((hi as u64) << 32) | lo as u64),
+    );
+
+    for (hi, lo) in hashes_tail {
+        if hi <= bound_hi {
+            out.push(((hi as u64) << 32) | lo as u64);
+        }
+    }
+}
+
+/// Seed xor'ed into the packed k-mer for the low half of 64-bit [`KmerMix`] hashes.
+const KMER_MIX_LO_SEED: u32 = 0x9e37_79b9;
+
+/// The mask of the low `2k` bits, and the shift of the first character of a packed k-mer.
+fn kmer_mix_params(k: usize) -> (u32, u32) {
+    assert!(
+        0 < k && k <= 16,
+        "KmerMix requires 0 < k <= 16, but k = {k}."
+    );
+    let mask = if k == 16 {
+        u32::MAX
+    } else {
+        (1 << (2 * k)) - 1
+    };
+    (mask, 2 * (k as u32 - 1))
+}
+
+/// Hash all k-mers by applying [`fmix32`] to the (canonical) packed k-mer, xor'ed with `seed`.
+/// Since both steps are bijections, this is injective on (canonical) k-mers.
+///
+/// Returns a SIMD iterator over 8 chunks of the sequence, and a scalar iterator over the tail.
+fn kmer_mix_simd<'s, const RC: bool, S: Seq<'s>>(
+    seq: S,
+    k: usize,
+    seed: u32,
+) -> (
+    impl ExactSizeIterator<Item = u32x8> + Clone,
+    impl Iterator<Item = u32> + Clone,
+) {
+    let (mask, shift) = kmer_mix_params(k);
+    assert_eq!(S::BITS_PER_CHAR, 2);
+
+    // Each k-mer is packed with its first character in the highest bits,
+    // so that comparing packed k-mers compares them lexicographically.
+    let (chars, tail) = seq.par_iter_bp(k);
+    let simd_mask = u32x8::splat(mask);
+    let simd_seed = u32x8::splat(seed);
+    let mut fw = u32x8::ZERO;
+    let mut rc = u32x8::ZERO;
+    let mut head = chars.map(move |c| {
+        fw = ((fw << 2) | c) & simd_mask;
+        let kmer = if RC {
+            rc = (rc >> 2) | ((c ^ u32x8::splat(2)) << shift);
+            fw.min(rc)
+        } else {
+            fw
+        };
+        fmix32_simd(kmer ^ simd_seed)
+    });
+    head.by_ref().take(k - 1).for_each(drop);
+
+    (head, kmer_mix_scalar::<RC, S>(tail, k, seed))
+}
+
+/// [`kmer_mix_simd`] for one k-mer at a time.
+fn kmer_mix_scalar<'s, const RC: bool, S: Seq<'s>>(
+    seq: S,
+    k: usize,
+    seed: u32,
+) -> impl Iterator<Item = u32> + Clone {
+    let (mask, shift) = kmer_mix_params(k);
+    let mut fw = 0u32;
+    let mut rc = 0u32;
+    seq.iter_bp()
+        .map(move |c| {
+            fw = ((fw << 2) | c as u32) & mask;
+            let kmer = if RC {
+                rc = (rc >> 2) | (((c ^ 2) as u32) << shift);
+                fw.min(rc)
+            } else {
+                fw
+            };
+            fmix32(kmer ^ seed)
+        })
+        .skip(k - 1)
+}
+
+/// The seed used by Mash.
+const MASH_SEED: u32 = 42;
+
+/// MurmurHash3 of all (canonical) k-mers, as computed by Mash.
+///
+/// For canonical hashes, the k-mer or its reverse complement is hashed,
+/// whichever is lexicographically smaller as a string.
+fn murmur3_seq<'s, const RC: bool, S: Seq<'s>, T>(
+    seq: S,
+    k: usize,
+    hash: impl Fn(&[u8]) -> T,
+) -> impl Iterator<Item = T> {
+    let ascii = seq
+        .iter_bp()
+        .map(|c| b"ACTG"[c as usize])
+        .collect::<Vec<_>>();
+    let mut rc = vec![0; k];
+    (0..(seq.len() + 1).saturating_sub(k)).map(move |i| {
+        let kmer = &ascii[i..i + k];
+        if RC {
+            for (r, &c) in std::iter::zip(&mut rc, kmer.iter().rev()) {
+                *r = match c {
+                    b'A' => b'T',
+                    b'C' => b'G',
+                    b'G' => b'C',
+                    _ => b'A',
+                };
+            }
+            if rc[..] < kmer[..] {
+                return hash(&rc);
+            }
+        }
+        hash(kmer)
+    })
+}
+
+/// MurmurHash3, x86 32-bit variant.
+fn murmur3_x86_32(key: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let mix_k1 = |k1: u32| k1.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+    let mut h1 = seed;
+    let blocks = key.chunks_exact(4);
+    let tail = blocks.remainder();
+    for block in blocks {
+        h1 ^= mix_k1(u32::from_le_bytes(block.try_into().unwrap()));
+        h1 = h1.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    if !tail.is_empty() {
+        let mut buf = [0; 4];
+        buf[..tail.len()].copy_from_slice(tail);
+        h1 ^= mix_k1(u32::from_le_bytes(buf));
+    }
+    fmix32(h1 ^ key.len() as u32)
+}
+
+/// MurmurHash3, x64 128-bit variant.
+fn murmur3_x64_128(key: &[u8], seed: u32) -> (u64, u64) {
+    const C1: u64 = 0x87c3_7b91_1142_53d5;
+    const C2: u64 = 0x4cf5_ad43_2745_937f;
+    let mix_k1 = |k1: u64| k1.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix_k2 = |k2: u64| k2.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+
+    let mut h1 = seed as u64;
+    let mut h2 = seed as u64;
+    let blocks = key.chunks_exact(16);
+    let tail = blocks.remainder();
+    for block in blocks {
+        let k1 = u64::from_le_bytes(block[..8].try_into().unwrap());
+        let k2 = u64::from_le_bytes(block[8..].try_into().unwrap());
+        h1 ^= mix_k1(k1);
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dc_e729);
+        h2 ^= mix_k2(k2);
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x3849_5ab5);
+    }
+
+    let mut buf = [0; 16];
+    buf[..tail.len()].copy_from_slice(tail);
+    if tail.len() > 8 {
+        h2 ^= mix_k2(u64::from_le_bytes(buf[8..].try_into().unwrap()));
+    }
+    if !tail.is_empty() {
+        h1 ^= mix_k1(u64::from_le_bytes(buf[..8].try_into().unwrap()));
+    }
+
+    h1 ^= key.len() as u64;
+    h2 ^= key.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix64(h1);
+    h2 = fmix64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    (h1, h2)
+}
+
+/// The 64-bit finalizer of MurmurHash3.
+fn fmix64(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^ (x >> 33)
+}
+
+/// The 32-bit finalizer of MurmurHash3, a bijection on `u32`.
+fn fmix32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^ (x >> 16)
+}
+
+/// [`fmix32`] on 8 lanes.
+fn fmix32_simd(mut x: u32x8) -> u32x8 {
+    x ^= x >> 16;
+    x = x * u32x8::splat(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x * u32x8::splat(0xc2b2_ae35);
+    x ^ (x >> 16)
+}
+
+/// Seed values for the low half of 64-bit hashes.
+/// These are the high halves of the original 64-bit ntHash seeds.
+const HASHES_LO: [u32; 4] = [
+    (0x3c8b_fbb3_95c6_0474u64 >> 32) as u32,
+    (0x3193_c185_62a0_2b4cu64 >> 32) as u32,
+    (0x2032_3ed0_8257_2324u64 >> 32) as u32,
+    (0x2955_49f5_4be2_4456u64 >> 32) as u32,
+];
+
+/// ntHash with different seed values, used for the low 32 bits of 64-bit hashes.
+#[derive(Clone)]
+pub(crate) struct NtHasherLo {
+    f: [u32; 4],
+    c: [u32; 4],
+    f_rot: [u32; 4],
+    c_rot: [u32; 4],
+    simd_f: u32x8,
+    simd_c: u32x8,
+    simd_f_rot: u32x8,
+    simd_c_rot: u32x8,
+}
+
+impl CharHasher for NtHasherLo {
+    fn new<'s, SEQ: Seq<'s>>(k: usize) -> Self {
+        assert_eq!(SEQ::BITS_PER_CHAR, 2);
+
+        let rot = k as u32 - 1;
+        let f = HASHES_LO;
+        let c = from_fn(|i| HASHES_LO[complement_base(i as u8) as usize]);
+        let f_rot = f.map(|h| h.rotate_left(rot));
+        let c_rot = c.map(|h| h.rotate_left(rot));
+        let idx = [0, 1, 2, 3, 0, 1, 2, 3];
+        Self {
+            f,
+            c,
+            f_rot,
+            c_rot,
+            simd_f: idx.map(|i| f[i]).into(),
+            simd_c: idx.map(|i| c[i]).into(),
+            simd_f_rot: idx.map(|i| f_rot[i]).into(),
+            simd_c_rot: idx.map(|i| c_rot[i]).into(),
+        }
+    }
+
+    fn f(&self, b: u8) -> u32 {
+        self.f[b as usize]
+    }
+    fn c(&self, b: u8) -> u32 {
+        self.c[b as usize]
+    }
+    fn f_rot(&self, b: u8) -> u32 {
+        self.f_rot[b as usize]
+    }
+    fn c_rot(&self, b: u8) -> u32 {
+        self.c_rot[b as usize]
+    }
+
+    fn simd_f(&self, b: u32x8) -> u32x8 {
+        intrinsics::table_lookup(self.simd_f, b)
+    }
+    fn simd_c(&self, b: u32x8) -> u32x8 {
+        intrinsics::table_lookup(self.simd_c, b)
+    }
+    fn simd_f_rot(&self, b: u32x8) -> u32x8 {
+        intrinsics::table_lookup(self.simd_f_rot, b)
+    }
+    fn simd_c_rot(&self, b: u32x8) -> u32x8 {
+        intrinsics::table_lookup(self.simd_c_rot, b)
+    }
+}
+
+/// FastMod32, using the low 32 bits of the hash.
+/// Taken from https://github.com/lemire/fastmod/blob/master/include/fastmod.h
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct FM32 {
+    d: u64,
+    m: u64,
+}
+impl FastMod<u32> for FM32 {
+    fn new(d: usize) -> Self {
+        Self {
+            d: d as u64,
+            m: u64::MAX / d as u64 + 1,
+        }
+    }
+    fn fastmod(self, h: u32) -> usize {
+        let lowbits = self.m.wrapping_mul(h as u64);
+        ((lowbits as u128 * self.d as u128) >> 64) as usize
+    }
+    fn fastdiv(self, h: u32) -> u32 {
+        ((self.m as u128 * h as u128) >> 64) as u32
+    }
+}
+
+/// FastMod64, the 64-bit variant of [`FM32`].
+/// Taken from https://github.com/lemire/fastmod/blob/master/include/fastmod.h
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct FM64 {
+    d: u64,
+    m: u128,
+}
+impl FM64 {
+    /// The high 64 bits of the 192-bit product `a * b`.
+    fn mul128_u64(a: u128, b: u64) -> u64 {
+        let lo = ((a as u64 as u128) * b as u128) >> 64;
+        let hi = (a >> 64) * b as u128 + lo;
+        (hi >> 64) as u64
+    }
+}
+impl FastMod<u64> for FM64 {
+    fn new(d: usize) -> Self {
+        Self {
+            d: d as u64,
+            m: u128::MAX / d as u128 + 1,
+        }
+    }
+    fn fastmod(self, h: u64) -> usize {
+        let lowbits = self.m.wrapping_mul(h as u128);
+        Self::mul128_u64(lowbits, self.d) as usize
+    }
+    fn fastdiv(self, h: u64) -> u64 {
+        Self::mul128_u64(self.m, h)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn fastmod() {
+    for d in (0..100).map(|_| rand::random_range(2..100000)) {
+        let m32 = FM32::new(d);
+        let m64 = FM64::new(d);
+        for h in (0..1000)
+            .map(|_| rand::random::<u64>())
+            .chain([0, u64::MAX])
+        {
+            assert_eq!(m32.fastmod(h as u32), h as u32 as usize % d);
+            assert_eq!(m32.fastdiv(h as u32), h as u32 / d as u32);
+            assert_eq!(m64.fastmod(h), (h % d as u64) as usize);
+            assert_eq!(m64.fastdiv(h), h / d as u64);
+        }
+    }
+}
+
+/// Generate a linear de Bruijn sequence of order `k`, containing every k-mer exactly once.
+#[cfg(test)]
+fn de_bruijn(k: usize) -> Vec<u8> {
+    fn rec(t: usize, p: usize, k: usize, a: &mut Vec<u8>, out: &mut Vec<u8>) {
+        if t > k {
+            if k.is_multiple_of(p) {
+                out.extend_from_slice(&a[1..=p]);
+            }
+        } else {
+            a[t] = a[t - p];
+            rec(t + 1, p, k, a, out);
+            for c in a[t - p] + 1..4 {
+                a[t] = c;
+                rec(t + 1, t, k, a, out);
+            }
+        }
+    }
+    let mut out = vec![];
+    rec(1, 1, k, &mut vec![0; k + 1], &mut out);
+    // Linearize the cyclic sequence.
+    out.extend_from_within(..k - 1);
+    out.into_iter().map(|c| b"ACTG"[c as usize]).collect()
+}
+
+#[cfg(test)]
+#[test]
+fn kmer_mix_injective() {
+    use packed_seq::{PackedSeqVec, SeqVec};
+
+    fn all_hashes<const RC: bool>(seq: &PackedSeqVec, k: usize) -> Vec<u32> {
+        let (head, tail) = kmer_mix_simd::<RC, _>(seq.as_slice(), k, 0);
+        let mut hashes = head
+            .flat_map(|x| x.to_array())
+            .chain(tail)
+            .collect::<Vec<_>>();
+        hashes.sort_unstable();
+        hashes
+    }
+
+    for k in 1..=10 {
+        let seq = PackedSeqVec::from_ascii(&de_bruijn(k));
+        let num_kmers = 4usize.pow(k as u32);
+        assert_eq!(seq.len(), num_kmers + k - 1);
+
+        // All forward k-mers have distinct hashes.
+        let mut hashes = all_hashes::<false>(&seq, k);
+        assert_eq!(hashes.len(), num_kmers);
+        hashes.dedup();
+        assert_eq!(hashes.len(), num_kmers);
+
+        // Canonical hashes are distinct for all k-mers that are not each other's reverse complement.
+        let mut hashes = all_hashes::<true>(&seq, k);
+        assert_eq!(hashes.len(), num_kmers);
+        hashes.dedup();
+        let palindromes = if k.is_multiple_of(2) {
+            4usize.pow(k as u32 / 2)
+        } else {
+            0
+        };
+        assert_eq!(hashes.len(), (num_kmers + palindromes) / 2);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn murmur3() {
+    assert_eq!(murmur3_x86_32(b"", 0), 0);
+    assert_eq!(
+        murmur3_x86_32(b"The quick brown fox jumps over the lazy dog", 0),
+        0x2e4ff723
+    );
+    assert_eq!(murmur3_x64_128(b"", 0), (0, 0));
+    assert_eq!(
+        murmur3_x64_128(b"The quick brown fox jumps over the lazy dog", 0),
+        (0xe34bbc7bbc071b6c, 0x7a433ca9c49a9347)
+    );
+
+    // Canonical hashes are equal for a k-mer and its reverse complement.
+    let hash = |seq: &[u8]| {
+        murmur3_seq::<true, _, _>(packed_seq::AsciiSeq(seq), seq.len(), |kmer| {
+            murmur3_x64_128(kmer, MASH_SEED).0
+        })
+        .next()
+        .unwrap()
+    };
+    assert_eq!(hash(b"AACGTTTG"), hash(b"CAAACGTT"));
+    assert_eq!(hash(b"CAAACGTT"), murmur3_x64_128(b"AACGTTTG", MASH_SEED).0);
+
+    // Agreement with the `murmur3` crate for all tail lengths.
+    let key = (0..100).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+    for len in 0..=key.len() {
+        let key = &key[..len];
+        let expected = ::murmur3::murmur3_x64_128(&mut &key[..], MASH_SEED).unwrap();
+        assert_eq!(
+            murmur3_x64_128(key, MASH_SEED),
+            (expected as u64, (expected >> 64) as u64)
+        );
+        let expected = ::murmur3::murmur3_32(&mut &key[..], MASH_SEED).unwrap();
+        assert_eq!(murmur3_x86_32(key, MASH_SEED), expected);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn collect_bound() {
+    // Hashes equal to the bound are kept in both the SIMD and the scalar path.
+    let head = u32x8::from([1, 5, 9, 5, 0, u32::MAX, 6, 4]);
+    let mut out = vec![];
+    collect32(std::iter::once(head), [5, 6, 3].into_iter(), 5, &mut out);
+    assert_eq!(out, [1, 5, 5, 0, 4, 5, 3]);
+
+    let hi = u32x8::from([1, 5, 9, 5, 0, u32::MAX, 6, 4]);
+    let lo = u32x8::splat(7);
+    let mut out = vec![];
+    collect64(
+        std::iter::once((hi, lo)),
+        [(5, 8), (6, 0)].into_iter(),
+        5 << 32 | 7,
+        &mut out,
+    );
+    // 64-bit hashes are only filtered on their high half.
+    out.sort_unstable();
+    assert_eq!(
+        out,
+        [
+            7,
+            1 << 32 | 7,
+            4 << 32 | 7,
+            5 << 32 | 7,
+            5 << 32 | 7,
+            5 << 32 | 8
+        ]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn simd_scalar() {
+    use packed_seq::{AsciiSeqVec, PackedSeqVec, SeqVec};
+
+    fn check<H: KmerHasher, const RC: bool>(hasher: H, seq: &PackedSeqVec, k: usize) {
+        let (head, tail) = hasher.hash32_simd::<RC, _>(seq.as_slice(), k);
+        let mut simd = head
+            .flat_map(|x| x.to_array())
+            .chain(tail)
+            .collect::<Vec<_>>();
+        let mut scalar = hasher
+            .hash32_scalar::<RC, _>(seq.as_slice(), k)
+            .collect::<Vec<_>>();
+        assert_eq!(scalar.len(), seq.len() - k + 1);
+        simd.sort_unstable();
+        scalar.sort_unstable();
+        assert_eq!(simd, scalar, "{hasher:?} k={k}");
+
+        let (head, tail) = hasher.hash64_simd::<RC, _>(seq.as_slice(), k);
+        let mut simd = head
+            .flat_map(|(hi, lo)| std::iter::zip(hi.to_array(), lo.to_array()))
+            .chain(tail)
+            .map(|(hi, lo)| ((hi as u64) << 32) | lo as u64)
+            .collect::<Vec<_>>();
+        let mut scalar = hasher
+            .hash64_scalar::<RC, _>(seq.as_slice(), k)
+            .collect::<Vec<_>>();
+        simd.sort_unstable();
+        scalar.sort_unstable();
+        assert_eq!(simd, scalar, "{hasher:?} k={k}");
+    }
+
+    for len in [16, 100, 1000] {
+        let seq = PackedSeqVec::random(len);
+        for k in [1, 5, 16] {
+            check::<_, false>(NtHash, &seq, k);
+            check::<_, true>(NtHash, &seq, k);
+            check::<_, true>(KmerMix, &seq, k);
+            check::<_, true>(Murmur3, &seq, k);
+            check::<_, true>(Seeded::new(KmerMix, 3), &seq, k);
+        }
+    }
+
+    // Seed 0 is the unseeded hasher, and other seeds permute hashes.
+    let seq = AsciiSeqVec::random(1000);
+    let hashes = |hasher: Seeded<NtHash>| {
+        hasher
+            .hash64_scalar::<true, _>(seq.as_slice(), 21)
+            .collect::<Vec<_>>()
+    };
+    let unseeded = NtHash
+        .hash64_scalar::<true, _>(seq.as_slice(), 21)
+        .collect::<Vec<_>>();
+    assert_eq!(hashes(Seeded::new(NtHash, 0)), unseeded);
+    assert_ne!(hashes(Seeded::new(NtHash, 1)), unseeded);
+    assert_ne!(Seeded::new(NtHash, 1).id(), NtHash.id());
+}
@@ -24,11 +24,11 @@ pub unsafe fn append_from_mask<T>(vals: S, mask: S, v: &mut [T], write_idx: &mut
     unsafe {
         use core::arch::x86_64::*;
 
-        let vals = transmute(vals);
+        let vals = transmute::<S, __m256i>(vals);
 
-        let m = _mm256_movemask_ps(transmute(!mask)) as usize;
+        let m = _mm256_movemask_ps(transmute::<S, __m256>(!mask)) as usize;
         let numberofnewvalues = L - m.count_ones() as usize;
-        let key = transmute(UNIQSHUF[m]);
+        let key = transmute::<S, __m256i>(UNIQSHUF[m]);
         let val = _mm256_permutevar8x32_epi32(vals, key);
         _mm256_storeu_si256(v.as_mut_ptr().add(*write_idx) as *mut __m256i, val);
         *write_idx += numberofnewvalues;
@@ -63,6 +63,50 @@ pub unsafe fn append_from_mask(vals: S, mask: S, v: &mut [u32], write_idx: &mut
     }
 }
 
+/// Given a 'table' `t` consisting of 8 values, and an index `idx` consisting of 8 indices from 0 to 4,
+/// look up the first four indices in the first half of `t`, and the second four indices in the second half of `t`.
+#[inline(always)]
+#[cfg(target_feature = "avx")]
+pub fn table_lookup(t: S, idx: S) -> S {
+    unsafe {
+        use core::arch::x86_64::{__m256, __m256i, _mm256_permutevar_ps};
+
+        transmute::<__m256, S>(_mm256_permutevar_ps(
+            transmute::<S, __m256>(t),
+            transmute::<S, __m256i>(idx),
+        ))
+    }
+}
+
+/// Given a 'table' `t` consisting of 8 values, and an index `idx` consisting of 8 indices from 0 to 4,
+/// look up the first four indices in the first half of `t`, and the second four indices in the second half of `t`.
+#[inline(always)]
+#[cfg(target_feature = "neon")]
+pub fn table_lookup(t: S, idx: S) -> S {
+    unsafe {
+        use core::arch::aarch64::{uint8x16_t, vqtbl1q_u8};
+
+        const OFFSET: u32 = 0x03_02_01_00;
+
+        let idx = idx * S::splat(0x04_04_04_04) + S::splat(OFFSET);
+        let (t1, t2): (uint8x16_t, uint8x16_t) = transmute(t);
+        let (i1, i2): (uint8x16_t, uint8x16_t) = transmute(idx);
+        let r1 = vqtbl1q_u8(t1, i1);
+        let r2 = vqtbl1q_u8(t2, i2);
+        transmute((r1, r2))
+    }
+}
+
+/// Given a 'table' `t` consisting of 8 values, and an index `idx` consisting of 8 indices from 0 to 4,
+/// look up the first four indices in the first half of `t`, and the second four indices in the second half of `t`.
+#[inline(always)]
+#[cfg(not(any(target_feature = "avx", target_feature = "neon")))]
+pub fn table_lookup(t: S, idx: S) -> S {
+    let t = t.as_array_ref();
+    let idx = idx.to_array();
+    S::new(core::array::from_fn(|i| t[(i / 4) * 4 + idx[i] as usize]))
+}
+
 /// For each of 256 masks of which elements are different than their predecessor,
 /// a shuffle that sends those new elements to the beginning.
 #[rustfmt::skip]
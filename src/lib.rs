@@ -7,11 +7,20 @@
 //! See the corresponding [blogpost](https://curiouscoding.nl/posts/simd-sketch/) for more background and an evaluation.
 //!
 //! ## Hash function
-//! All internal hashes are 32 bits. Either a forward-only hash or
+//! By default, all internal hashes are 32 bits. Either a forward-only hash or
 //! reverse-complement-aware (canonical) hash can be used.
 //!
-//! **TODO:** Current we use (canonical) ntHash. This causes some hash-collisions
-//! for `k <= 16`, [which could be avoided](https://curiouscoding.nl/posts/nthash/#is-nthash-injective-on-kmers).
+//! For large inputs approaching 1Gbp and beyond, 32-bit hashes become dense and collision-prone.
+//! Set [`Sketcher::hash_bits`] to 64 to use 64-bit hashes instead. Sketches record their hash width,
+//! and 32-bit and 64-bit sketches can not be compared.
+//!
+//! By default we use (canonical) ntHash. This causes some hash-collisions
+//! for `k <= 16`, [which can be avoided](https://curiouscoding.nl/posts/nthash/#is-nthash-injective-on-kmers)
+//! by using the [`KmerMix`] hasher via [`Sketcher::with_hasher`], which is injective on (canonical) k-mers.
+//! Other hash functions can be used by implementing [`KmerHasher`].
+//! [`Sketcher::seed`] remixes the hashes of any hasher with a seed (see [`Seeded`]).
+//! Sketches with different seeds are independent replicates, which can be used to estimate
+//! or reduce the variance of the estimators. Sketches record their seed, and only sketches with equal seeds can be compared.
 //!
 //! ## BucketSketch
 //! For classic bottom-sketch, evaluating the similarity is slow because a
@@ -27,9 +36,19 @@
 //!
 //! The bucket sketch similarity has a very strong one-to-one correlation with the classic bottom-sketch.
 //!
-//! **TODO:** A drawback of this method is that some buckets may remain empty
-//! when the input sequences are not long enough.  In that case, _densification_
-//! could be applied, but this is not currently implemented. If you need this, please reach out.
+//! A drawback of this method is that some buckets may remain empty
+//! when the input sequences are not long enough. By default, these are tracked
+//! via a bitmask (when [`Sketcher::filter_empty`] is set) and ignored when both sketches have them empty.
+//! Alternatively, set [`Sketcher::densify`] to fill empty buckets using
+//! [optimal densification](https://proceedings.mlr.press/v70/shrivastava17a.html):
+//! each empty bucket copies the value of a pseudo-random non-empty bucket.
+//! This keeps the estimator unbiased for short sequences such as plasmids.
+//!
+//! ## ScaledSketch
+//! For inputs of wildly different sizes, such as a genome and a metagenome, fixed-size sketches
+//! do not work well. [`Sketcher::scaled_sketch`] instead returns a FracMinHash [`ScaledSketch`],
+//! as used by [sourmash](https://sourmash.readthedocs.io/), containing all hashes up to `MAX / scale`.
+//! Its size grows linearly with the number of distinct k-mers in the input.
 //!
 //! ## Jaccard similarity
 //! For the bottom sketch, we conceptually estimate similarity as follows:
@@ -39,12 +58,63 @@
 //! For the bucket sketch, we simply return the fraction of partitions that have
 //! the same k-mer for both sequences.
 //!
+//! ## Containment
+//! Besides the Jaccard similarity, all sketch types can estimate the containment `|A ∩ B| / |A|`
+//! via `containment`, and the containment of the smaller input in the larger one via `max_containment`.
+//! Bottom sketches with full hashes and scaled sketches compute this directly from the shared hashes.
+//! Other sketches convert the Jaccard similarity into a containment using
+//! the number of distinct k-mers of each input, as estimated while sketching.
+//!
+//! ## Cardinality
+//! Each sketch stores the estimated number of distinct k-mers of its input, returned by `cardinality`,
+//! next to the total sequence length returned by `seq_len`.
+//! Bottom sketches derive it from the largest of the `s` smallest hashes, bucket sketches from the
+//! bucket minima, and scaled sketches multiply their number of hashes by the scale.
+//! `set_sizes` estimates the numbers of distinct k-mers in the union and intersection of two inputs.
+//!
+//! ## Metadata
+//! Each sketch has a `name` and [`Metadata`] about its input: the source path, the number of records,
+//! and the number of `G` and `C` characters. These are set by the file sketching APIs such as
+//! `Sketcher::bottom_sketch_files`, and stored with the sketch by [`serialize`].
+//!
+//! ## Abundances
+//! When [`Sketcher::track_abundance`] is set, bottom sketches storing full hashes and scaled sketches
+//! also store how often each sampled k-mer occurs in the input.
+//! Their `weighted_similarity` estimates the weighted Jaccard similarity `sum min(a_x, b_x) / sum max(a_x, b_x)`,
+//! and `weighted_containment` the fraction of all k-mer occurrences of one input whose k-mer occurs in the other.
+//! Merging sketches adds up their abundances.
+//!
+//! ## Mash distance and ANI
+//! The `distance` methods additionally return the [Mash](https://doi.org/10.1186/s13059-016-0997-x)
+//! distance `-1/k * ln(2J / (1+J))`, the corresponding ANI estimate, and the Mash p-value
+//! of observing the number of shared hashes for random inputs of the same sequence lengths.
+//! [`mash_distance`], [`ani`], and [`mash_p_value`] compute these for arbitrary `k`, `s`, and lengths.
+//!
+//! [`Sketcher::new_mash`] creates bottom sketches using canonical MurmurHash3 with Mash's seed,
+//! as Mash does. [`mash::read_msh`] and [`mash::write_msh`] read and write them in Mash's `.msh` format.
+//! Both are only tested against self-generated fixtures, not against the output of Mash itself.
+//! Similarly, [`Sketcher::new_sourmash`] creates bottom and scaled sketches using sourmash's hashes.
+//! With the `sourmash` feature, these can be exchanged via the JSON signatures read and written by
+//! `sourmash::read_signatures` and `sourmash::write_signatures`.
+//!
 //! ## b-bit sketches
 //!
 //! Instead of storing the full 32-bit hashes, it is sufficient to only store the low bits of each hash.
 //! In practice, `b=8` is usually fine.
 //! When extra fast comparisons are needed, use `b=1` in combination with a 3 to 4x larger `s`.
 //!
+//! Bottom sketches store full hashes by default, independent of `b`.
+//! When [`Sketcher::b_bit_bottom`] is set, they first select the bottom `s` full hashes,
+//! and then only keep their low `b` bits, as a sorted multiset.
+//! Their similarity counts pairs of equal values between the two sketches,
+//! and subtracts the expected number of accidental matches, `s^2 / 2^b`.
+//! This estimate of the number of shared hashes `c` is converted to a Jaccard similarity
+//! using the number of distinct k-mers of both inputs, as estimated while sketching.
+//! For inputs of equal size, this is `c / (2s - c)`.
+//! The noise in the number of accidental matches is around `s / 2^(b/2)`,
+//! i.e. 6% of `s` for `b=8`, so b-bit bottom sketches require `b=16` or more.
+//! Only bottom sketches that store full hashes can be merged.
+//!
 //! ## Usage
 //!
 //! The main entrypoint of this library is the [`Sketcher`] object.
@@ -85,16 +155,47 @@
 //! let similarity: f32 = sketch1.similarity(&sketch2);
 //! ```
 //!
-//! **TODO:** Currently there is no support yet for merging sketches, or for
-//! sketching multiple sequences into one sketch. It's not hard, I just need to find a good API.
-//! Please reach out if you're interested in this.
+//! To sketch multiple sequences (e.g. the contigs of an assembly) into a single sketch,
+//! use [`Sketcher::bottom_sketch_seqs`] or [`Sketcher::sketch_seqs`]. These only hash k-mers
+//! that are fully contained in one of the sequences.
+//! When the sequences do not fit in memory, such as a large stream of reads, push them one at a time into a
+//! [`SketchBuilder`] instead, created by [`Sketcher::bottom_sketch_builder`] or [`Sketcher::sketch_builder`].
+//!
+//! To sketch many inputs in parallel, use [`Sketcher::sketch_many`]. With the `fastx` feature,
+//! `Sketcher::sketch_files`, `Sketcher::bottom_sketch_files`, and `Sketcher::scaled_sketch_files`
+//! read and sketch (compressed) FASTA/FASTQ files in parallel, and return an error for each file that can not be read.
+//! `with_fastx_records` reads the records of a single file, together with their ids.
+//!
+//! For ASCII input (e.g. [`packed_seq::AsciiSeq`]), set [`Sketcher::skip_non_acgt`] to exclude
+//! all k-mers overlapping a non-`ACGT` character, such as `N` runs in scaffolds or IUPAC codes.
+//! The input is then split into maximal `ACGT` runs, each of which is still processed using SIMD.
+//!
+//! When sketching raw reads, singleton k-mers caused by sequencing errors dominate the smallest hashes.
+//! Set [`Sketcher::min_count`] to only sketch k-mers occurring at least that many times, as for `mash sketch -m`.
+//! Sketches record the min count they were built with.
 //!
-//! **TODO:** If you would like a binary instead of a library, again, please reach out :)
+//! Sketches of different inputs can be merged into the sketch of their union using
+//! [`BottomSketch::merge`] and [`BucketSketch::merge`], or [`BottomSketch::merge_all`] and
+//! [`BucketSketch::merge_all`] for more than two sketches.
+//! Bucket sketches can only be merged when they store full hashes (`b=32`) and are not densified.
+//!
+//! Sketches can be stored using `write` or `to_bytes`, and loaded again using `read` or `from_bytes`.
+//! The versioned binary format is described in [`serialize`].
+//!
+//! ## Command line tool
+//!
+//! The `simd-sketch` binary (built with `--features cli`, e.g. `cargo install simd-sketch --features cli`)
+//! sketches FASTA/FASTQ files into sketch files using its `sketch` subcommand,
+//! and compares them using `dist` and `triangle`.
+//! `info` prints the parameters of stored sketches, and `merge` merges them.
 //!
 //! ## Implementation notes
 //!
 //! This library works by partitioning the input sequence into 8 chunks,
 //! and processing those in parallel using SIMD.
+//! Sequences with more than [`Sketcher::chunk_len`] k-mers are additionally split into chunks overlapping by `k-1` characters,
+//! whose hashes are collected on multiple threads. Since collected hashes are only sorted or assigned to buckets afterwards,
+//! the resulting sketch is identical to the single-threaded one.
 //! This is based on the [`packed-seq`](../packed_seq/index.html) and [`simd-minimizers`](../simd_minimizers/index.html) crates.
 //!
 //! For bottom sketch, the largest hash should be around `target = u32::MAX * s / n` (ignoring duplicates).
@@ -125,16 +226,43 @@
 //! As an example, when sketching 5MB bacterial genomes using `s=10000`, each sketch takes 4ms.
 //! Comparing two sketches takes 1.6us.
 //! This starts to be the dominant factor when the number of input sequences is more than 5000.
+//! [`BucketSketch::similarity_matrix`] and [`BottomSketch::similarity_matrix`] compare all pairs of sketches at once,
+//! using all threads and processing the sketches in cache-sized tiles.
+//! When only the most similar pairs are needed, [`BucketSketch::search`] and [`BottomSketch::search`]
+//! instead return the references above a similarity threshold and/or the top `k` references for each query.
+//! For `b=1` bucket sketches, comparisons stop early once the threshold can not be reached anymore.
 
+mod builder;
+mod distance;
+#[cfg(feature = "fastx")]
+mod files;
+mod hash;
 mod intrinsics;
+pub mod mash;
+mod matrix;
+mod search;
+pub mod serialize;
+#[cfg(feature = "sourmash")]
+pub mod sourmash;
 
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
 
-use packed_seq::{u32x8, Seq};
-use simd_minimizers::private::nthash::NtHasher;
+pub use builder::SketchBuilder;
+pub use distance::{Distance, ani, mash_distance, mash_p_value};
+#[cfg(feature = "fastx")]
+pub use files::with_fastx_records;
+pub use hash::{HasherId, KmerHasher, KmerMix, Murmur3, NtHash, Seeded};
+pub use matrix::SimilarityMatrix;
+pub use search::Hit;
+
+use hash::{FastMod, HashValue};
+use packed_seq::Seq;
+use rayon::prelude::*;
 use tracing::{debug, info};
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum BitSketch {
+    B64(Vec<u64>),
     B32(Vec<u32>),
     B16(Vec<u16>),
     B8(Vec<u8>),
@@ -142,48 +270,445 @@ pub enum BitSketch {
 }
 
 impl BitSketch {
-    fn new(b: usize, vals: Vec<u32>) -> Self {
+    fn new<T: HashValue>(b: usize, vals: Vec<T>) -> Self {
+        assert!(
+            b <= T::BITS,
+            "Bit width {b} exceeds hash width {}.",
+            T::BITS
+        );
         match b {
-            32 => BitSketch::B32(vals),
-            16 => BitSketch::B16(vals.into_iter().map(|x| x as u16).collect()),
-            8 => BitSketch::B8(vals.into_iter().map(|x| x as u8).collect()),
+            64 => BitSketch::B64(vals.into_iter().map(|x| x.to_u64()).collect()),
+            32 => BitSketch::B32(vals.into_iter().map(|x| x.to_u64() as u32).collect()),
+            16 => BitSketch::B16(vals.into_iter().map(|x| x.to_u64() as u16).collect()),
+            8 => BitSketch::B8(vals.into_iter().map(|x| x.to_u64() as u8).collect()),
             1 => BitSketch::B1({
                 assert_eq!(vals.len() % 64, 0);
                 vals.chunks_exact(64)
                     .map(|xs| {
                         xs.iter()
                             .enumerate()
-                            .fold(0u64, |bits, (i, x)| bits | (((x & 1) as u64) << i))
+                            .fold(0u64, |bits, (i, x)| bits | ((x.to_u64() & 1) << i))
                     })
                     .collect()
             }),
-            _ => panic!("Unsupported bit width. Must be 1 or 8 or 16 or 32."),
+            _ => panic!("Unsupported bit width. Must be 1 or 8 or 16 or 32 or 64."),
+        }
+    }
+}
+
+/// An estimate of the containment of the k-mers of one input in another.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Containment {
+    /// The estimated containment, between 0 and 1.
+    pub containment: f32,
+    /// The number of hashes (buckets, for [`BucketSketch`]) that are shared.
+    pub shared: usize,
+    /// The number of hashes (buckets, for [`BucketSketch`]) the estimate is based on.
+    pub total: usize,
+}
+
+impl Containment {
+    /// Convert a Jaccard similarity into a containment of `a` in `b`, given their numbers of distinct k-mers.
+    fn from_jaccard(jaccard: f32, a: u64, b: u64, shared: usize, total: usize) -> Self {
+        let jaccard = jaccard.clamp(0.0, 1.0) as f64;
+        let intersection = jaccard * (a + b) as f64 / (1.0 + jaccard);
+        let containment = if a == 0 {
+            0.0
+        } else {
+            (intersection / a as f64).min(1.0) as f32
+        };
+        Containment {
+            containment,
+            shared,
+            total,
+        }
+    }
+
+    /// The larger of two containments.
+    fn max(self, other: Self) -> Self {
+        if other.containment > self.containment {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// Estimates of the numbers of distinct k-mers in the union and intersection of two inputs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SetSizes {
+    /// The estimated number of distinct k-mers in either input.
+    pub union: u64,
+    /// The estimated number of distinct k-mers in both inputs.
+    pub intersection: u64,
+}
+
+impl SetSizes {
+    /// Derive the sizes from a Jaccard similarity and the numbers of distinct k-mers of both inputs.
+    fn from_jaccard(jaccard: f32, a: u64, b: u64) -> Self {
+        let jaccard = jaccard.clamp(0.0, 1.0) as f64;
+        let union = (a + b) as f64 / (1.0 + jaccard);
+        Self::new(union, jaccard * union)
+    }
+
+    fn new(union: f64, intersection: f64) -> Self {
+        SetSizes {
+            union: union.round() as u64,
+            intersection: intersection.round() as u64,
+        }
+    }
+}
+
+/// Information about the sketched input, set by the file sketching APIs such as `Sketcher::bottom_sketch_files`.
+///
+/// The parameters used to build a sketch, such as `k` and `s`, are available via its getters.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// The path of the file the input was read from. Empty when unknown.
+    pub path: String,
+    /// The number of sequence records in the input, when known.
+    pub records: Option<u64>,
+    /// The number of `G` and `C` characters in the input, when known.
+    pub gc: Option<u64>,
+}
+
+impl Metadata {
+    /// The fraction of `G` and `C` characters in an input of total length `seq_len`, when known.
+    pub fn gc_content(&self, seq_len: u64) -> Option<f64> {
+        let gc = self.gc?;
+        Some(if seq_len == 0 {
+            0.0
+        } else {
+            gc as f64 / seq_len as f64
+        })
+    }
+
+    /// The metadata of the union of two inputs.
+    /// The path is kept when it is equal for both, and counts are added when known for both.
+    fn merge(&self, other: &Self) -> Self {
+        let add = |a: Option<u64>, b: Option<u64>| Some(a? + b?);
+        Metadata {
+            path: if self.path == other.path {
+                self.path.clone()
+            } else {
+                String::new()
+            },
+            records: add(self.records, other.records),
+            gc: add(self.gc, other.gc),
         }
     }
 }
 
 /// A sketch containing the `s` smallest k-mer hashes.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BottomSketch {
     rc: bool,
     k: usize,
+    s: usize,
     b: usize,
-    bottom: Vec<u32>,
+    /// The width of the hashes: 32 or 64.
+    hash_bits: usize,
+    hasher: HasherId,
+    /// The number of distinct hashes in the sketch, at most `s`.
+    len: usize,
+    /// The estimated number of distinct k-mers in the input.
+    kmers: u64,
+    /// A name for the sketched input, such as a file or sequence name. Empty by default.
+    pub name: String,
+    /// Information about the sketched input. Empty by default.
+    pub metadata: Metadata,
+    /// The total length of the sketched sequences.
+    seq_len: u64,
+    /// Only k-mers occurring at least this many times in the input were sketched.
+    min_count: usize,
+    /// When `b` equals `hash_bits`, the sorted full hashes, padded to length `s` with `MAX`.
+    /// Otherwise, the sorted low `b` bits of the `len` smallest hashes.
+    bottom: BitSketch,
+    /// The number of occurrences of each of the `len` hashes, when [`Sketcher::track_abundance`] was set.
+    abundances: Option<Vec<u64>>,
 }
 
 impl BottomSketch {
+    /// The hash function and seed used to build the sketch.
+    pub fn hasher(&self) -> HasherId {
+        self.hasher
+    }
+
+    /// The k-mer length.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Whether canonical (reverse-complement aware) k-mer hashes were used.
+    pub fn rc(&self) -> bool {
+        self.rc
+    }
+
+    /// The width of the hashes: 32 or 64.
+    pub fn hash_bits(&self) -> usize {
+        self.hash_bits
+    }
+
+    /// The total length of the sketched sequences.
+    pub fn seq_len(&self) -> u64 {
+        self.seq_len
+    }
+
+    /// The minimal number of occurrences of the sketched k-mers. See [`Sketcher::min_count`].
+    pub fn min_count(&self) -> usize {
+        self.min_count
+    }
+
+    /// The sketch size `s`.
+    pub fn s(&self) -> usize {
+        self.s
+    }
+
+    /// The estimated number of distinct k-mers in the input,
+    /// derived from the largest of the `s` smallest hashes.
+    pub fn cardinality(&self) -> u64 {
+        self.kmers
+    }
+
+    /// The number of bits stored per value.
+    pub fn b(&self) -> usize {
+        self.b
+    }
+
+    /// The number of occurrences in the input of each hash in the sketch, in increasing order of hash,
+    /// when [`Sketcher::track_abundance`] was set.
+    pub fn abundances(&self) -> Option<&[u64]> {
+        self.abundances.as_deref()
+    }
+
     /// Compute the similarity between two `BottomSketch`es.
     pub fn similarity(&self, other: &Self) -> f32 {
+        self.check_compatible(other);
+        if self.b < self.hash_bits {
+            return self.b_bit_similarity(other);
+        }
+        match (&self.bottom, &other.bottom) {
+            (BitSketch::B32(a), BitSketch::B32(b)) => {
+                Self::inner_similarity(&a[..self.len], &b[..other.len], self.s.min(other.s))
+            }
+            (BitSketch::B64(a), BitSketch::B64(b)) => {
+                Self::inner_similarity(&a[..self.len], &b[..other.len], self.s.min(other.s))
+            }
+            _ => panic!("Bit width mismatch"),
+        }
+    }
+
+    fn check_compatible(&self, other: &Self) {
         assert_eq!(self.rc, other.rc);
         assert_eq!(self.k, other.k);
         assert_eq!(self.b, other.b);
-        let a = &self.bottom;
-        let b = &other.bottom;
-        assert_eq!(a.len(), b.len());
+        assert_eq!(
+            self.hash_bits, other.hash_bits,
+            "Cannot compare 32-bit and 64-bit sketches."
+        );
+        assert_eq!(
+            self.hasher, other.hasher,
+            "Cannot compare sketches using different hash functions."
+        );
+    }
+
+    /// Estimate the similarity from the number of pairs of equal `b`-bit values,
+    /// correcting for accidental matches.
+    fn b_bit_similarity(&self, other: &Self) -> f32 {
+        let (_, intersection) = self.b_bit_intersection(other);
+        let union = (self.kmers + other.kmers) as f64 - intersection;
+        if union <= 0.0 {
+            return 1.0;
+        }
+        (intersection / union).clamp(0.0, 1.0) as f32
+    }
+
+    /// Estimate the number of hashes shared between two `b`-bit sketches, and the size of the intersection of their inputs.
+    fn b_bit_intersection(&self, other: &Self) -> (f64, f64) {
+        let pairs = match (&self.bottom, &other.bottom) {
+            (BitSketch::B32(a), BitSketch::B32(b)) => Self::equal_pairs(a, b),
+            (BitSketch::B16(a), BitSketch::B16(b)) => Self::equal_pairs(a, b),
+            (BitSketch::B8(a), BitSketch::B8(b)) => Self::equal_pairs(a, b),
+            _ => panic!("Bit width mismatch"),
+        };
+        let (na, nb) = (self.len as f64, other.len as f64);
+        // Each of the `na * nb` pairs matches accidentally with probability `p`.
+        let p = 0.5f64.powi(self.b as i32);
+        let shared = ((pairs as f64 - na * nb * p) / (1.0 - p)).clamp(0.0, na.min(nb));
+        // A shared k-mer is in both sketches when it is below the threshold of both,
+        // which happens at the smaller of the two sampling rates.
+        let rate = |len: f64, kmers: u64| if kmers == 0 { 1.0 } else { len / kmers as f64 };
+        let rate = rate(na, self.kmers).min(rate(nb, other.kmers));
+        let intersection = if rate > 0.0 { shared / rate } else { 0.0 };
+        (shared, intersection)
+    }
+
+    /// Estimate the fraction of k-mers of `self` that are also in `other`.
+    ///
+    /// For sketches storing full hashes, this is the fraction of hashes of `self`
+    /// below the largest hash of both sketches that occur in `other`.
+    /// Otherwise, this is derived from the similarity and the estimated number of distinct k-mers in both inputs.
+    pub fn containment(&self, other: &Self) -> Containment {
+        self.check_compatible(other);
+        if self.b < self.hash_bits {
+            let (shared, intersection) = self.b_bit_intersection(other);
+            return Containment {
+                containment: if self.kmers == 0 {
+                    0.0
+                } else {
+                    (intersection / self.kmers as f64).min(1.0) as f32
+                },
+                shared: shared.round() as usize,
+                total: self.len,
+            };
+        }
+        let (shared, total) = match (&self.bottom, &other.bottom) {
+            (BitSketch::B32(a), BitSketch::B32(b)) => {
+                Self::inner_containment(&a[..self.len], &b[..other.len], other.len < b.len())
+            }
+            (BitSketch::B64(a), BitSketch::B64(b)) => {
+                Self::inner_containment(&a[..self.len], &b[..other.len], other.len < b.len())
+            }
+            _ => panic!("Bit width mismatch"),
+        };
+        Containment {
+            containment: if total == 0 {
+                0.0
+            } else {
+                shared as f32 / total as f32
+            },
+            shared,
+            total,
+        }
+    }
+
+    /// Estimate the containment of the smaller input in the larger one.
+    pub fn max_containment(&self, other: &Self) -> Containment {
+        self.containment(other).max(other.containment(self))
+    }
+
+    /// Compute the Mash distance, ANI, and Mash p-value, as `mash dist` does.
+    ///
+    /// For sketches storing full hashes, the p-value is based on the number of hashes among
+    /// the `s` smallest hashes of the union of both sketches.
+    /// For b-bit sketches, where the union is unknown, it is based on the larger sketch length.
+    pub fn distance(&self, other: &Self) -> Distance {
+        self.check_compatible(other);
+        let s = self.s.min(other.s);
+        let total = match (&self.bottom, &other.bottom) {
+            _ if self.b < self.hash_bits => self.len.max(other.len),
+            (BitSketch::B32(a), BitSketch::B32(b)) => {
+                Self::inner_union(&a[..self.len], &b[..other.len], s).1
+            }
+            (BitSketch::B64(a), BitSketch::B64(b)) => {
+                Self::inner_union(&a[..self.len], &b[..other.len], s).1
+            }
+            _ => panic!("Bit width mismatch"),
+        };
+        Distance::new(
+            self.similarity(other),
+            total,
+            self.k,
+            self.seq_len,
+            other.seq_len,
+        )
+    }
+
+    /// Estimate the numbers of distinct k-mers in the union and intersection of both inputs.
+    ///
+    /// For sketches storing full hashes, the union is estimated from the `s` smallest hashes
+    /// of the union of both sketches, and the intersection is the similarity times the union.
+    /// Otherwise, the intersection is estimated as for [`BottomSketch::containment`].
+    pub fn set_sizes(&self, other: &Self) -> SetSizes {
+        self.check_compatible(other);
+        if self.b < self.hash_bits {
+            let (_, intersection) = self.b_bit_intersection(other);
+            let intersection = intersection.min(self.kmers.min(other.kmers) as f64);
+            let union = (self.kmers + other.kmers) as f64 - intersection;
+            return SetSizes::new(union, intersection);
+        }
+        let s = self.s.min(other.s);
+        let union = match (&self.bottom, &other.bottom) {
+            (BitSketch::B32(a), BitSketch::B32(b)) => {
+                bottom_kmers(&union_bottom(&a[..self.len], &b[..other.len], s), s)
+            }
+            (BitSketch::B64(a), BitSketch::B64(b)) => {
+                bottom_kmers(&union_bottom(&a[..self.len], &b[..other.len], s), s)
+            }
+            _ => panic!("Bit width mismatch"),
+        } as f64;
+        SetSizes::new(union, self.similarity(other) as f64 * union)
+    }
+
+    /// Count the hashes in `a` that are covered by `b`, and how many of them are in `b`.
+    /// When `b` is not `complete` (it has `s` hashes), it only covers hashes up to its largest one.
+    /// All of `a` is in the range covered by `a` itself.
+    fn inner_containment<T: Ord>(a: &[T], b: &[T], b_complete: bool) -> (usize, usize) {
+        let a = &a[..Self::covered(a, b, b_complete)];
+        let mut shared = 0;
+        let mut i = 0;
+        let mut j = 0;
+        while i < a.len() && j < b.len() {
+            shared += (a[i] == b[j]) as usize;
+            let di = (a[i] <= b[j]) as usize;
+            let dj = (a[i] >= b[j]) as usize;
+            i += di;
+            j += dj;
+        }
+        (shared, a.len())
+    }
+
+    /// The length of the prefix of `a` covered by `b`, as for [`BottomSketch::inner_containment`].
+    fn covered<T: Ord>(a: &[T], b: &[T], b_complete: bool) -> usize {
+        match b.last() {
+            Some(max) if !b_complete => a.partition_point(|x| x <= max),
+            _ => a.len(),
+        }
+    }
+
+    /// The number of pairs `(i, j)` with `a[i] == b[j]`, for sorted `a` and `b`.
+    fn equal_pairs<T: Ord>(a: &[T], b: &[T]) -> usize {
+        let mut pairs = 0;
+        let mut i = 0;
+        let mut j = 0;
+        while i < a.len() && j < b.len() {
+            if a[i] < b[j] {
+                i += 1;
+            } else if a[i] > b[j] {
+                j += 1;
+            } else {
+                let i0 = i;
+                let j0 = j;
+                while i < a.len() && a[i] == a[i0] {
+                    i += 1;
+                }
+                while j < b.len() && b[j] == b[j0] {
+                    j += 1;
+                }
+                pairs += (i - i0) * (j - j0);
+            }
+        }
+        pairs
+    }
+
+    /// The fraction of the `s` smallest hashes in the union of `a` and `b` that are in both,
+    /// computed the same way as `mash dist`.
+    fn inner_similarity<T: Ord>(a: &[T], b: &[T], s: usize) -> f32 {
+        let (intersection_size, union_size) = Self::inner_union(a, b, s);
+        if union_size == 0 {
+            return 1.0;
+        }
+        intersection_size as f32 / union_size as f32
+    }
+
+    /// The number of shared hashes among the `s` smallest hashes of the union of both sketches,
+    /// and the number of hashes considered, as in Mash.
+    fn inner_union<T: Ord>(a: &[T], b: &[T], s: usize) -> (usize, usize) {
         let mut intersection_size = 0;
         let mut union_size = 0;
         let mut i = 0;
         let mut j = 0;
-        while union_size < a.len() {
+        while union_size < s && i < a.len() && j < b.len() {
             intersection_size += (a[i] == b[j]) as usize;
             let di = (a[i] <= b[j]) as usize;
             let dj = (a[i] >= b[j]) as usize;
@@ -191,59 +716,451 @@ impl BottomSketch {
             j += dj;
             union_size += 1;
         }
+        // When one sketch runs out, the union continues with the other.
+        union_size += (s - union_size).min(a.len() - i);
+        union_size += (s - union_size).min(b.len() - j);
+        (intersection_size, union_size)
+    }
+
+    /// Estimate the weighted Jaccard similarity `sum_x min(a_x, b_x) / sum_x max(a_x, b_x)`
+    /// of the k-mer abundances `a_x` and `b_x` of both inputs,
+    /// over the `s` smallest hashes in the union of both sketches.
+    ///
+    /// Requires full hashes and abundances in both sketches (see [`Sketcher::track_abundance`]).
+    pub fn weighted_similarity(&self, other: &Self) -> f32 {
+        self.check_compatible(other);
+        let (wa, wb) = (self.expect_abundances(), other.expect_abundances());
+        let (min, max) = match (&self.bottom, &other.bottom) {
+            (BitSketch::B32(a), BitSketch::B32(b)) => {
+                weighted_union(&a[..self.len], wa, &b[..other.len], wb, self.s.min(other.s))
+            }
+            (BitSketch::B64(a), BitSketch::B64(b)) => {
+                weighted_union(&a[..self.len], wa, &b[..other.len], wb, self.s.min(other.s))
+            }
+            _ => panic!("Bit width mismatch"),
+        };
+        if max == 0 {
+            return 1.0;
+        }
+        (min as f64 / max as f64) as f32
+    }
+
+    /// Estimate the abundance-weighted containment of `self` in `other`:
+    /// the fraction of all k-mer occurrences of `self` whose k-mer also occurs in `other`.
+    ///
+    /// As for [`BottomSketch::containment`], only hashes of `self` below the largest hash of both sketches are used.
+    /// Requires full hashes and abundances in `self` (see [`Sketcher::track_abundance`]).
+    pub fn weighted_containment(&self, other: &Self) -> f32 {
+        self.check_compatible(other);
+        let wa = self.expect_abundances();
+        let (shared, total) = match (&self.bottom, &other.bottom) {
+            (BitSketch::B32(a), BitSketch::B32(b)) => {
+                let a = &a[..self.len];
+                let a = &a[..Self::covered(a, &b[..other.len], other.len < b.len())];
+                weighted_shared(a, wa, &b[..other.len])
+            }
+            (BitSketch::B64(a), BitSketch::B64(b)) => {
+                let a = &a[..self.len];
+                let a = &a[..Self::covered(a, &b[..other.len], other.len < b.len())];
+                weighted_shared(a, wa, &b[..other.len])
+            }
+            _ => panic!("Bit width mismatch"),
+        };
+        if total == 0 {
+            return 0.0;
+        }
+        (shared as f64 / total as f64) as f32
+    }
+
+    fn expect_abundances(&self) -> &[u64] {
+        assert_eq!(
+            self.b, self.hash_bits,
+            "Weighted estimators require sketches storing full hashes."
+        );
+        self.abundances
+            .as_deref()
+            .expect("Weighted estimators require sketches with abundances.")
+    }
+
+    /// Merge two `BottomSketch`es into the sketch of the union of their inputs.
+    pub fn merge(&self, other: &Self) -> Self {
+        Self::merge_all([self, other])
+    }
+
+    /// Merge any non-zero number of `BottomSketch`es into the sketch of the union of their inputs.
+    ///
+    /// Requires full hashes (`b` equal to the hash width), equal `s`, and equal [`Sketcher::min_count`].
+    pub fn merge_all<'a>(sketches: impl IntoIterator<Item = &'a Self>) -> Self {
+        let sketches = sketches.into_iter().collect::<Vec<_>>();
+        let first = sketches.first().expect("Cannot merge zero sketches.");
+        assert_eq!(
+            first.b, first.hash_bits,
+            "Only sketches storing full hashes (b={}) can be merged.",
+            first.hash_bits
+        );
+        let mut bottom = first.bottom.clone();
+        let mut len = first.len;
+        let mut seq_len = first.seq_len;
+        let mut metadata = first.metadata.clone();
+        for other in &sketches[1..] {
+            seq_len += other.seq_len;
+            metadata = metadata.merge(&other.metadata);
+            assert_eq!(first.rc, other.rc);
+            assert_eq!(first.k, other.k);
+            assert_eq!(first.b, other.b);
+            assert_eq!(first.hash_bits, other.hash_bits);
+            assert_eq!(first.hasher, other.hasher);
+            assert_eq!(first.min_count, other.min_count);
+            assert_eq!(
+                first.s, other.s,
+                "Cannot merge sketches of different sizes."
+            );
+            len = match (&mut bottom, &other.bottom) {
+                (BitSketch::B32(a), BitSketch::B32(b)) => {
+                    Self::merge_sorted(a, len, &b[..other.len])
+                }
+                (BitSketch::B64(a), BitSketch::B64(b)) => {
+                    Self::merge_sorted(a, len, &b[..other.len])
+                }
+                _ => panic!("Bit width mismatch"),
+            };
+        }
+        let (kmers, abundances) = match &bottom {
+            BitSketch::B32(h) => (
+                bottom_kmers(&h[..len], first.s),
+                sum_abundances(
+                    &h[..len],
+                    sketches.iter().map(|s| match &s.bottom {
+                        BitSketch::B32(x) => (&x[..s.len], s.abundances()),
+                        _ => panic!("Bit width mismatch"),
+                    }),
+                ),
+            ),
+            BitSketch::B64(h) => (
+                bottom_kmers(&h[..len], first.s),
+                sum_abundances(
+                    &h[..len],
+                    sketches.iter().map(|s| match &s.bottom {
+                        BitSketch::B64(x) => (&x[..s.len], s.abundances()),
+                        _ => panic!("Bit width mismatch"),
+                    }),
+                ),
+            ),
+            _ => panic!("Bit width mismatch"),
+        };
+        BottomSketch {
+            len,
+            kmers,
+            seq_len,
+            metadata,
+            bottom,
+            abundances,
+            ..(*first).clone()
+        }
+    }
 
-        return intersection_size as f32 / a.len() as f32;
+    /// Replace `a` by the `a.len()` smallest distinct values in `a[..len]` and `b`,
+    /// and return their number.
+    fn merge_sorted<T: HashValue>(a: &mut Vec<T>, len: usize, b: &[T]) -> usize {
+        let s = a.len();
+        a.truncate(len);
+        a.extend_from_slice(b);
+        a.sort_unstable();
+        a.dedup();
+        a.truncate(s);
+        let len = a.len();
+        a.resize(s, T::MAX);
+        len
     }
 }
 
 /// A sketch containing the smallest k-mer hash for each remainder mod `s`.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BucketSketch {
     rc: bool,
     k: usize,
     b: usize,
+    /// The width of the hashes: 32 or 64.
+    hash_bits: usize,
+    hasher: HasherId,
+    /// Whether empty buckets were filled using densification.
+    densified: bool,
+    /// The estimated number of distinct k-mers in the input.
+    kmers: u64,
+    /// A name for the sketched input, such as a file or sequence name. Empty by default.
+    pub name: String,
+    /// Information about the sketched input. Empty by default.
+    pub metadata: Metadata,
+    /// The total length of the sketched sequences.
+    seq_len: u64,
+    /// Only k-mers occurring at least this many times in the input were sketched.
+    min_count: usize,
     pub buckets: BitSketch,
     empty: Vec<u64>,
 }
 
 impl BucketSketch {
-    /// Compute the similarity between two `BucketSketch`es.
-    pub fn similarity(&self, other: &Self) -> f32 {
+    /// The hash function and seed used to build the sketch.
+    pub fn hasher(&self) -> HasherId {
+        self.hasher
+    }
+
+    /// The k-mer length.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Whether canonical (reverse-complement aware) k-mer hashes were used.
+    pub fn rc(&self) -> bool {
+        self.rc
+    }
+
+    /// The width of the hashes: 32 or 64.
+    pub fn hash_bits(&self) -> usize {
+        self.hash_bits
+    }
+
+    /// The total length of the sketched sequences.
+    pub fn seq_len(&self) -> u64 {
+        self.seq_len
+    }
+
+    /// The minimal number of occurrences of the sketched k-mers. See [`Sketcher::min_count`].
+    pub fn min_count(&self) -> usize {
+        self.min_count
+    }
+
+    /// The number of bits stored per value.
+    pub fn b(&self) -> usize {
+        self.b
+    }
+
+    /// Whether empty buckets were filled using densification.
+    pub fn densified(&self) -> bool {
+        self.densified
+    }
+
+    /// The estimated number of distinct k-mers in the input, derived from the bucket minima.
+    pub fn cardinality(&self) -> u64 {
+        self.kmers
+    }
+
+    fn check_compatible(&self, other: &Self) {
         assert_eq!(self.rc, other.rc);
         assert_eq!(self.k, other.k);
         assert_eq!(self.b, other.b);
+        assert_eq!(
+            self.hash_bits, other.hash_bits,
+            "Cannot compare 32-bit and 64-bit sketches."
+        );
+        assert_eq!(
+            self.hasher, other.hasher,
+            "Cannot compare sketches using different hash functions."
+        );
+        assert_eq!(
+            self.densified, other.densified,
+            "Cannot compare densified and non-densified sketches."
+        );
+    }
+
+    /// Compute the similarity between two `BucketSketch`es.
+    pub fn similarity(&self, other: &Self) -> f32 {
+        self.check_compatible(other);
+        let (matches, total) = self.matches(other);
+        Self::similarity_from_matches(self.b, matches, total)
+    }
+
+    fn similarity_from_matches(b: usize, matches: usize, total: usize) -> f32 {
+        let f = matches as f32 / total as f32;
+        // Correction for accidental matches.
+        let bb = 2f32.powi(b as i32);
+        (bb * f - 1.0) / (bb - 1.0)
+    }
+
+    /// The number of equal buckets, and the number of buckets that are not empty in both sketches.
+    fn matches(&self, other: &Self) -> (usize, usize) {
         let both_empty = self.both_empty(other);
         if both_empty > 0 {
             info!("Both empty: {}", both_empty);
         }
-        match (&self.buckets, &other.buckets) {
-            (BitSketch::B32(a), BitSketch::B32(b)) => Self::inner_similarity(a, b, both_empty),
-            (BitSketch::B16(a), BitSketch::B16(b)) => Self::inner_similarity(a, b, both_empty),
-            (BitSketch::B8(a), BitSketch::B8(b)) => Self::inner_similarity(a, b, both_empty),
-            (BitSketch::B1(a), BitSketch::B1(b)) => Self::b1_similarity(a, b, both_empty),
+        let matches = match (&self.buckets, &other.buckets) {
+            (BitSketch::B64(a), BitSketch::B64(b)) => Self::inner_matches(a, b),
+            (BitSketch::B32(a), BitSketch::B32(b)) => Self::inner_matches(a, b),
+            (BitSketch::B16(a), BitSketch::B16(b)) => Self::inner_matches(a, b),
+            (BitSketch::B8(a), BitSketch::B8(b)) => Self::inner_matches(a, b),
+            (BitSketch::B1(a), BitSketch::B1(b)) => Self::b1_matches(a, b),
             _ => panic!("Bit width mismatch"),
-        }
+        };
+        (matches, self.s() - both_empty)
     }
-    fn inner_similarity<T: Eq>(a: &Vec<T>, b: &Vec<T>, both_empty: usize) -> f32 {
+
+    fn inner_matches<T: Eq>(a: &[T], b: &[T]) -> usize {
         assert_eq!(a.len(), b.len());
-        let f = std::iter::zip(a, b)
-            .map(|(a, b)| (a == b) as u32)
-            .sum::<u32>() as f32
-            / (a.len() - both_empty) as f32;
-        // Correction for accidental matches.
-        let bb = (1usize << (size_of::<T>() * 8)) as f32;
-        (bb * f - 1.0) / (bb - 1.0)
-        // f
+        if size_of::<T>() > 2 {
+            return std::iter::zip(a, b)
+                .map(|(a, b)| (a == b) as u32)
+                .sum::<u32>() as usize;
+        }
+        // For 8 and 16 bit values, count matches per lane in `u8`s, so that the compiler
+        // vectorizes this into narrow compares, and flush the counts before they can overflow.
+        const L: usize = 32;
+        let mut matches = 0;
+        for (a, b) in std::iter::zip(a.chunks(255 * L), b.chunks(255 * L)) {
+            let (a, a_tail) = a.as_chunks::<L>();
+            let (b, b_tail) = b.as_chunks::<L>();
+            let mut counts = [0u8; L];
+            for (a, b) in std::iter::zip(a, b) {
+                for ((c, a), b) in counts.iter_mut().zip(a).zip(b) {
+                    *c += (a == b) as u8;
+                }
+            }
+            matches += counts.iter().map(|&c| c as usize).sum::<usize>();
+            matches += std::iter::zip(a_tail, b_tail)
+                .filter(|(a, b)| a == b)
+                .count();
+        }
+        matches
     }
 
-    fn b1_similarity(a: &Vec<u64>, b: &Vec<u64>, both_empty: usize) -> f32 {
+    fn b1_matches(a: &[u64], b: &[u64]) -> usize {
         assert_eq!(a.len(), b.len());
-        let f = std::iter::zip(a, b)
+        std::iter::zip(a, b)
             .map(|(a, b)| (*a ^ *b).count_zeros())
-            .sum::<u32>() as f32
-            / (64 * a.len() - both_empty) as f32;
+            .sum::<u32>() as usize
+    }
 
-        // Correction for accidental matches.
-        2. * f - 1.
+    /// The number of buckets `s`.
+    pub fn s(&self) -> usize {
+        match &self.buckets {
+            BitSketch::B64(h) => h.len(),
+            BitSketch::B32(h) => h.len(),
+            BitSketch::B16(h) => h.len(),
+            BitSketch::B8(h) => h.len(),
+            BitSketch::B1(h) => 64 * h.len(),
+        }
+    }
+
+    /// Estimate the fraction of k-mers of `self` that are also in `other`.
+    ///
+    /// This is derived from the similarity and the estimated number of distinct k-mers in both inputs.
+    pub fn containment(&self, other: &Self) -> Containment {
+        self.check_compatible(other);
+        let (shared, total) = self.matches(other);
+        Containment::from_jaccard(
+            self.similarity(other),
+            self.kmers,
+            other.kmers,
+            shared,
+            total,
+        )
+    }
+
+    /// Estimate the containment of the smaller input in the larger one.
+    pub fn max_containment(&self, other: &Self) -> Containment {
+        self.containment(other).max(other.containment(self))
+    }
+
+    /// Estimate the numbers of distinct k-mers in the union and intersection of both inputs,
+    /// from the similarity and the estimated number of distinct k-mers in both inputs.
+    pub fn set_sizes(&self, other: &Self) -> SetSizes {
+        self.check_compatible(other);
+        SetSizes::from_jaccard(self.similarity(other), self.kmers, other.kmers)
+    }
+
+    /// Compute the Mash distance, ANI, and Mash p-value,
+    /// with the p-value based on the number of buckets that are not empty in both sketches.
+    pub fn distance(&self, other: &Self) -> Distance {
+        self.check_compatible(other);
+        let (_, total) = self.matches(other);
+        Distance::new(
+            self.similarity(other),
+            total,
+            self.k,
+            self.seq_len,
+            other.seq_len,
+        )
+    }
+
+    /// Merge two `BucketSketch`es into the sketch of the union of their inputs.
+    ///
+    /// Requires full hashes (`b` equal to the hash width), non-densified sketches, equal `s`, and equal [`Sketcher::min_count`].
+    pub fn merge(&self, other: &Self) -> Self {
+        Self::merge_all([self, other])
+    }
+
+    /// Merge any non-zero number of `BucketSketch`es into the sketch of the union of their inputs,
+    /// by taking the element-wise minimum.
+    ///
+    /// Requires full hashes (`b` equal to the hash width), non-densified sketches, equal `s`, and equal [`Sketcher::min_count`].
+    pub fn merge_all<'a>(sketches: impl IntoIterator<Item = &'a Self>) -> Self {
+        let mut sketches = sketches.into_iter();
+        let first = sketches.next().expect("Cannot merge zero sketches.");
+        assert!(
+            !first.densified,
+            "Densified sketches cannot be merged. Merge before densifying instead."
+        );
+        assert_eq!(
+            first.b, first.hash_bits,
+            "Only sketches storing full hashes (b={}) can be merged.",
+            first.hash_bits
+        );
+        let mut buckets = first.buckets.clone();
+        let mut empty = first.empty.clone();
+        let mut seq_len = first.seq_len;
+        let mut metadata = first.metadata.clone();
+        for other in sketches {
+            seq_len += other.seq_len;
+            metadata = metadata.merge(&other.metadata);
+            assert_eq!(first.rc, other.rc);
+            assert_eq!(first.k, other.k);
+            assert_eq!(first.b, other.b);
+            assert_eq!(first.hash_bits, other.hash_bits);
+            assert_eq!(first.hasher, other.hasher);
+            assert_eq!(first.min_count, other.min_count);
+            assert_eq!(first.densified, other.densified);
+            assert_eq!(
+                first.s(),
+                other.s(),
+                "Cannot merge sketches of different sizes."
+            );
+            match (&mut buckets, &other.buckets) {
+                (BitSketch::B32(a), BitSketch::B32(b)) => Self::merge_min(a, b),
+                (BitSketch::B64(a), BitSketch::B64(b)) => Self::merge_min(a, b),
+                _ => panic!("Bit width mismatch"),
+            }
+            // A bucket is empty when it is empty in all sketches.
+            // An absent bitmask means no buckets are empty.
+            if other.empty.is_empty() {
+                empty.clear();
+            } else {
+                for (x, y) in std::iter::zip(&mut empty, &other.empty) {
+                    *x &= *y;
+                }
+            }
+        }
+        if empty.iter().all(|&x| x == 0) {
+            empty.clear();
+        }
+        let kmers = match &buckets {
+            BitSketch::B32(h) => bucket_kmers(h),
+            BitSketch::B64(h) => bucket_kmers(h),
+            _ => panic!("Bit width mismatch"),
+        };
+        BucketSketch {
+            buckets,
+            empty,
+            kmers,
+            seq_len,
+            metadata,
+            ..first.clone()
+        }
+    }
+
+    fn merge_min<T: Ord + Copy>(a: &mut [T], b: &[T]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in std::iter::zip(a, b) {
+            *x = (*x).min(*y);
+        }
     }
 
     fn both_empty(&self, other: &Self) -> usize {
@@ -253,49 +1170,633 @@ impl BucketSketch {
     }
 }
 
-/// An object containing the sketch parameters.
+/// A FracMinHash sketch containing all k-mer hashes up to [`ScaledSketch::max_hash`], about `MAX / scale`.
 ///
-/// Contains internal state to optimize the implementation when sketching multiple similar sequences.
-pub struct Sketcher {
+/// Unlike [`BottomSketch`] and [`BucketSketch`], its size grows linearly with the number of distinct k-mers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScaledSketch {
     rc: bool,
     k: usize,
-    s: usize,
-    b: usize,
-    pub filter_empty: bool,
-
-    factor: AtomicUsize,
+    /// The width of the hashes: 32 or 64.
+    hash_bits: usize,
+    hasher: HasherId,
+    /// Hashes are kept when they are at most [`ScaledSketch::max_hash`].
+    scale: u64,
+    /// A name for the sketched input, such as a file or sequence name. Empty by default.
+    pub name: String,
+    /// Information about the sketched input. Empty by default.
+    pub metadata: Metadata,
+    /// The total length of the sketched sequences.
+    seq_len: u64,
+    /// Only k-mers occurring at least this many times in the input were sketched.
+    min_count: usize,
+    /// The sorted distinct hashes, as `B32` or `B64` depending on `hash_bits`.
+    hashes: BitSketch,
+    /// The number of occurrences of each hash, when [`Sketcher::track_abundance`] was set.
+    abundances: Option<Vec<u64>>,
 }
 
-impl Sketcher {
-    /// Default sketcher that very fast at comparisons, but 20% slower at sketching.
-    /// Use for >= 50000 seqs, and safe default when input sequences are > 500'000 characters.
-    ///
-    /// When sequences are < 100'000 characters, inaccuracies may occur due to empty buckets.
-    pub fn default(k: usize) -> Self {
-        Sketcher {
-            rc: true,
-            k,
-            s: 32768,
-            b: 1,
-            filter_empty: false,
-            factor: 2.into(),
-        }
+impl ScaledSketch {
+    /// The hash function and seed used to build the sketch.
+    pub fn hasher(&self) -> HasherId {
+        self.hasher
     }
 
-    /// Default sketcher that is fast at sketching, but somewhat slower at comparisons.
-    /// Use for <= 5000 seqs, or when input sequences are < 100'000 characters.
-    pub fn default_fast_sketching(k: usize) -> Self {
-        Sketcher {
-            rc: true,
-            k,
-            s: 8192,
-            b: 8,
-            filter_empty: false,
-            factor: 2.into(),
-        }
+    /// The k-mer length.
+    pub fn k(&self) -> usize {
+        self.k
     }
 
-    /// Construct a new forward-only `Sketcher` object.
+    /// Whether canonical (reverse-complement aware) k-mer hashes were used.
+    pub fn rc(&self) -> bool {
+        self.rc
+    }
+
+    /// The width of the hashes: 32 or 64.
+    pub fn hash_bits(&self) -> usize {
+        self.hash_bits
+    }
+
+    /// The total length of the sketched sequences.
+    pub fn seq_len(&self) -> u64 {
+        self.seq_len
+    }
+
+    /// The minimal number of occurrences of the sketched k-mers. See [`Sketcher::min_count`].
+    pub fn min_count(&self) -> usize {
+        self.min_count
+    }
+
+    /// The scale of the sketch: on average, one in `scale` distinct k-mers is kept.
+    pub fn scale(&self) -> u64 {
+        self.scale
+    }
+
+    /// The largest hash kept at this scale: `MAX / scale`, computed in `f64` as sourmash does.
+    pub fn max_hash(&self) -> u64 {
+        max_hash_for_scale(self.hash_bits, self.scale)
+    }
+
+    /// The estimated number of distinct k-mers in the input: `len * scale`.
+    pub fn cardinality(&self) -> u64 {
+        self.len() as u64 * self.scale
+    }
+
+    /// The number of hashes in the sketch.
+    pub fn len(&self) -> usize {
+        match &self.hashes {
+            BitSketch::B32(h) => h.len(),
+            BitSketch::B64(h) => h.len(),
+            _ => panic!("Bit width mismatch"),
+        }
+    }
+
+    /// Whether the sketch contains no hashes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of occurrences in the input of each hash in the sketch, in increasing order of hash,
+    /// when [`Sketcher::track_abundance`] was set.
+    pub fn abundances(&self) -> Option<&[u64]> {
+        self.abundances.as_deref()
+    }
+
+    fn check_compatible(&self, other: &Self) {
+        assert_eq!(self.rc, other.rc);
+        assert_eq!(self.k, other.k);
+        assert_eq!(
+            self.hash_bits, other.hash_bits,
+            "Cannot compare 32-bit and 64-bit sketches."
+        );
+        assert_eq!(
+            self.hasher, other.hasher,
+            "Cannot compare sketches using different hash functions."
+        );
+    }
+
+    /// Compute the Jaccard similarity between two `ScaledSketch`es.
+    ///
+    /// When the scales differ, both sketches are first downsampled to the largest scale.
+    pub fn similarity(&self, other: &Self) -> f32 {
+        self.check_compatible(other);
+        let (intersection, a, b) = self.counts(other);
+        let union = a + b - intersection;
+        if union == 0 {
+            return 1.0;
+        }
+        intersection as f32 / union as f32
+    }
+
+    /// Estimate the fraction of k-mers of `self` that are also in `other`,
+    /// as the fraction of hashes of `self` that are in `other`, at the largest of both scales.
+    pub fn containment(&self, other: &Self) -> Containment {
+        self.check_compatible(other);
+        let (shared, total, _) = self.counts(other);
+        Containment {
+            containment: if total == 0 {
+                0.0
+            } else {
+                shared as f32 / total as f32
+            },
+            shared,
+            total,
+        }
+    }
+
+    /// Estimate the containment of the smaller input in the larger one.
+    pub fn max_containment(&self, other: &Self) -> Containment {
+        self.containment(other).max(other.containment(self))
+    }
+
+    /// Estimate the numbers of distinct k-mers in the union and intersection of both inputs,
+    /// as the numbers of hashes in the union and intersection of both sketches times the largest scale.
+    pub fn set_sizes(&self, other: &Self) -> SetSizes {
+        self.check_compatible(other);
+        let (intersection, a, b) = self.counts(other);
+        let scale = self.scale.max(other.scale) as f64;
+        SetSizes::new(
+            (a + b - intersection) as f64 * scale,
+            intersection as f64 * scale,
+        )
+    }
+
+    /// Compute the Mash distance, ANI, and Mash p-value,
+    /// with the p-value based on the number of hashes in the union of both sketches.
+    pub fn distance(&self, other: &Self) -> Distance {
+        self.check_compatible(other);
+        let (intersection, a, b) = self.counts(other);
+        Distance::new(
+            self.similarity(other),
+            a + b - intersection,
+            self.k,
+            self.seq_len,
+            other.seq_len,
+        )
+    }
+
+    /// The sizes of the intersection, `self`, and `other`, downsampled to the largest scale.
+    fn counts(&self, other: &Self) -> (usize, usize, usize) {
+        let scale = self.scale.max(other.scale);
+        match (&self.hashes, &other.hashes) {
+            (BitSketch::B32(a), BitSketch::B32(b)) => Self::inner_counts(a, b, scale),
+            (BitSketch::B64(a), BitSketch::B64(b)) => Self::inner_counts(a, b, scale),
+            _ => panic!("Bit width mismatch"),
+        }
+    }
+
+    /// The sizes of the intersection of `a` and `b`, `a`, and `b`, downsampled to `scale`.
+    fn inner_counts<T: HashValue>(a: &[T], b: &[T], scale: u64) -> (usize, usize, usize) {
+        let a = Self::downsample_slice(a, scale);
+        let b = Self::downsample_slice(b, scale);
+        let mut intersection = 0;
+        let mut i = 0;
+        let mut j = 0;
+        while i < a.len() && j < b.len() {
+            intersection += (a[i] == b[j]) as usize;
+            let di = (a[i] <= b[j]) as usize;
+            let dj = (a[i] >= b[j]) as usize;
+            i += di;
+            j += dj;
+        }
+        (intersection, a.len(), b.len())
+    }
+
+    /// The prefix of the sorted hashes `a` kept at `scale`.
+    fn downsample_slice<T: HashValue>(a: &[T], scale: u64) -> &[T] {
+        let bound = max_hash_for_scale(T::BITS, scale);
+        &a[..a.partition_point(|h| h.to_u64() <= bound)]
+    }
+
+    /// Return the sketch with only the hashes kept at `scale`, for a `scale` at least the current scale.
+    pub fn downsample(&self, scale: u64) -> Self {
+        assert!(
+            scale >= self.scale,
+            "Cannot downsample from scale {} to smaller scale {scale}.",
+            self.scale
+        );
+        let hashes = match &self.hashes {
+            BitSketch::B32(h) => BitSketch::B32(Self::downsample_slice(h, scale).to_vec()),
+            BitSketch::B64(h) => BitSketch::B64(Self::downsample_slice(h, scale).to_vec()),
+            _ => panic!("Bit width mismatch"),
+        };
+        let mut sketch = ScaledSketch {
+            scale,
+            hashes,
+            ..self.clone()
+        };
+        let len = sketch.len();
+        if let Some(abundances) = &mut sketch.abundances {
+            abundances.truncate(len);
+        }
+        sketch
+    }
+
+    /// Compute the weighted Jaccard similarity `sum_x min(a_x, b_x) / sum_x max(a_x, b_x)`
+    /// of the k-mer abundances `a_x` and `b_x` of both inputs, at the largest of both scales.
+    ///
+    /// Requires abundances in both sketches (see [`Sketcher::track_abundance`]).
+    pub fn weighted_similarity(&self, other: &Self) -> f32 {
+        self.check_compatible(other);
+        let (wa, wb) = (self.expect_abundances(), other.expect_abundances());
+        let scale = self.scale.max(other.scale);
+        let (min, max) = match (&self.hashes, &other.hashes) {
+            (BitSketch::B32(a), BitSketch::B32(b)) => {
+                let (a, b) = (
+                    Self::downsample_slice(a, scale),
+                    Self::downsample_slice(b, scale),
+                );
+                weighted_union(a, wa, b, wb, usize::MAX)
+            }
+            (BitSketch::B64(a), BitSketch::B64(b)) => {
+                let (a, b) = (
+                    Self::downsample_slice(a, scale),
+                    Self::downsample_slice(b, scale),
+                );
+                weighted_union(a, wa, b, wb, usize::MAX)
+            }
+            _ => panic!("Bit width mismatch"),
+        };
+        if max == 0 {
+            return 1.0;
+        }
+        (min as f64 / max as f64) as f32
+    }
+
+    /// Estimate the abundance-weighted containment of `self` in `other`:
+    /// the fraction of all k-mer occurrences of `self` whose k-mer also occurs in `other`,
+    /// at the largest of both scales.
+    ///
+    /// Requires abundances in `self` (see [`Sketcher::track_abundance`]).
+    pub fn weighted_containment(&self, other: &Self) -> f32 {
+        self.check_compatible(other);
+        let wa = self.expect_abundances();
+        let scale = self.scale.max(other.scale);
+        let (shared, total) = match (&self.hashes, &other.hashes) {
+            (BitSketch::B32(a), BitSketch::B32(b)) => {
+                weighted_shared(Self::downsample_slice(a, scale), wa, b)
+            }
+            (BitSketch::B64(a), BitSketch::B64(b)) => {
+                weighted_shared(Self::downsample_slice(a, scale), wa, b)
+            }
+            _ => panic!("Bit width mismatch"),
+        };
+        if total == 0 {
+            return 0.0;
+        }
+        (shared as f64 / total as f64) as f32
+    }
+
+    fn expect_abundances(&self) -> &[u64] {
+        self.abundances
+            .as_deref()
+            .expect("Weighted estimators require sketches with abundances.")
+    }
+
+    /// Merge two `ScaledSketch`es into the sketch of the union of their inputs.
+    pub fn merge(&self, other: &Self) -> Self {
+        Self::merge_all([self, other])
+    }
+
+    /// Merge any non-zero number of `ScaledSketch`es into the sketch of the union of their inputs,
+    /// at the largest of their scales. Requires equal [`Sketcher::min_count`].
+    pub fn merge_all<'a>(sketches: impl IntoIterator<Item = &'a Self>) -> Self {
+        let sketches = sketches.into_iter().collect::<Vec<_>>();
+        let first = sketches.first().expect("Cannot merge zero sketches.");
+        let scale = sketches.iter().map(|s| s.scale).max().unwrap();
+        let mut hashes = first.downsample(scale).hashes;
+        for other in &sketches[1..] {
+            first.check_compatible(other);
+            assert_eq!(first.min_count, other.min_count);
+            match (&mut hashes, &other.hashes) {
+                (BitSketch::B32(a), BitSketch::B32(b)) => {
+                    a.extend_from_slice(Self::downsample_slice(b, scale))
+                }
+                (BitSketch::B64(a), BitSketch::B64(b)) => {
+                    a.extend_from_slice(Self::downsample_slice(b, scale))
+                }
+                _ => panic!("Bit width mismatch"),
+            }
+        }
+        let abundances = match &mut hashes {
+            BitSketch::B32(h) => {
+                h.sort_unstable();
+                h.dedup();
+                sum_abundances(
+                    h,
+                    sketches.iter().map(|s| match &s.hashes {
+                        BitSketch::B32(x) => (&x[..], s.abundances()),
+                        _ => panic!("Bit width mismatch"),
+                    }),
+                )
+            }
+            BitSketch::B64(h) => {
+                h.sort_unstable();
+                h.dedup();
+                sum_abundances(
+                    h,
+                    sketches.iter().map(|s| match &s.hashes {
+                        BitSketch::B64(x) => (&x[..], s.abundances()),
+                        _ => panic!("Bit width mismatch"),
+                    }),
+                )
+            }
+            _ => panic!("Bit width mismatch"),
+        };
+        ScaledSketch {
+            scale,
+            hashes,
+            seq_len: sketches.iter().map(|s| s.seq_len).sum(),
+            metadata: sketches[1..]
+                .iter()
+                .fold(first.metadata.clone(), |m, s| m.merge(&s.metadata)),
+            abundances,
+            ..(*first).clone()
+        }
+    }
+}
+/// A sketch of any type, such as read from a file containing different types of sketches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnySketch {
+    Bottom(BottomSketch),
+    Bucket(BucketSketch),
+    Scaled(ScaledSketch),
+}
+
+impl From<BottomSketch> for AnySketch {
+    fn from(sketch: BottomSketch) -> Self {
+        AnySketch::Bottom(sketch)
+    }
+}
+
+impl From<BucketSketch> for AnySketch {
+    fn from(sketch: BucketSketch) -> Self {
+        AnySketch::Bucket(sketch)
+    }
+}
+
+impl From<ScaledSketch> for AnySketch {
+    fn from(sketch: ScaledSketch) -> Self {
+        AnySketch::Scaled(sketch)
+    }
+}
+
+impl AnySketch {
+    /// The type of the sketch: `bottom`, `bucket`, or `scaled`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AnySketch::Bottom(_) => "bottom",
+            AnySketch::Bucket(_) => "bucket",
+            AnySketch::Scaled(_) => "scaled",
+        }
+    }
+
+    /// The name of the sketched input.
+    pub fn name(&self) -> &str {
+        match self {
+            AnySketch::Bottom(s) => &s.name,
+            AnySketch::Bucket(s) => &s.name,
+            AnySketch::Scaled(s) => &s.name,
+        }
+    }
+
+    /// Information about the sketched input.
+    pub fn metadata(&self) -> &Metadata {
+        match self {
+            AnySketch::Bottom(s) => &s.metadata,
+            AnySketch::Bucket(s) => &s.metadata,
+            AnySketch::Scaled(s) => &s.metadata,
+        }
+    }
+
+    /// The k-mer length.
+    pub fn k(&self) -> usize {
+        match self {
+            AnySketch::Bottom(s) => s.k,
+            AnySketch::Bucket(s) => s.k,
+            AnySketch::Scaled(s) => s.k,
+        }
+    }
+
+    /// The total length of the sketched sequences.
+    pub fn seq_len(&self) -> u64 {
+        match self {
+            AnySketch::Bottom(s) => s.seq_len,
+            AnySketch::Bucket(s) => s.seq_len,
+            AnySketch::Scaled(s) => s.seq_len,
+        }
+    }
+
+    /// The minimal number of occurrences of the sketched k-mers. See [`Sketcher::min_count`].
+    pub fn min_count(&self) -> usize {
+        match self {
+            AnySketch::Bottom(s) => s.min_count,
+            AnySketch::Bucket(s) => s.min_count,
+            AnySketch::Scaled(s) => s.min_count,
+        }
+    }
+
+    /// The estimated number of distinct k-mers in the input.
+    pub fn cardinality(&self) -> u64 {
+        match self {
+            AnySketch::Bottom(s) => s.cardinality(),
+            AnySketch::Bucket(s) => s.cardinality(),
+            AnySketch::Scaled(s) => s.cardinality(),
+        }
+    }
+
+    /// Whether `self` and `other` can be compared: they must have the same type,
+    /// and were built using the same k-mers and hashes.
+    /// Bottom and bucket sketches must also store the same number of bits per value.
+    pub fn is_compatible(&self, other: &Self) -> bool {
+        match (self, other) {
+            (AnySketch::Bottom(a), AnySketch::Bottom(b)) => {
+                (a.rc, a.k, a.hash_bits, a.hasher, a.b) == (b.rc, b.k, b.hash_bits, b.hasher, b.b)
+            }
+            (AnySketch::Bucket(a), AnySketch::Bucket(b)) => {
+                (a.rc, a.k, a.hash_bits, a.hasher, a.b, a.s(), a.densified)
+                    == (b.rc, b.k, b.hash_bits, b.hasher, b.b, b.s(), b.densified)
+            }
+            (AnySketch::Scaled(a), AnySketch::Scaled(b)) => {
+                (a.rc, a.k, a.hash_bits, a.hasher) == (b.rc, b.k, b.hash_bits, b.hasher)
+            }
+            _ => false,
+        }
+    }
+
+    /// Compute the similarity between two sketches of the same type.
+    pub fn similarity(&self, other: &Self) -> f32 {
+        match (self, other) {
+            (AnySketch::Bottom(a), AnySketch::Bottom(b)) => a.similarity(b),
+            (AnySketch::Bucket(a), AnySketch::Bucket(b)) => a.similarity(b),
+            (AnySketch::Scaled(a), AnySketch::Scaled(b)) => a.similarity(b),
+            _ => panic!("Cannot compare sketches of different types."),
+        }
+    }
+
+    /// Estimate the numbers of distinct k-mers in the union and intersection of the inputs of two sketches of the same type.
+    pub fn set_sizes(&self, other: &Self) -> SetSizes {
+        match (self, other) {
+            (AnySketch::Bottom(a), AnySketch::Bottom(b)) => a.set_sizes(b),
+            (AnySketch::Bucket(a), AnySketch::Bucket(b)) => a.set_sizes(b),
+            (AnySketch::Scaled(a), AnySketch::Scaled(b)) => a.set_sizes(b),
+            _ => panic!("Cannot compare sketches of different types."),
+        }
+    }
+
+    /// Compute the Mash distance, ANI, and p-value between two sketches of the same type.
+    pub fn distance(&self, other: &Self) -> Distance {
+        match (self, other) {
+            (AnySketch::Bottom(a), AnySketch::Bottom(b)) => a.distance(b),
+            (AnySketch::Bucket(a), AnySketch::Bucket(b)) => a.distance(b),
+            (AnySketch::Scaled(a), AnySketch::Scaled(b)) => a.distance(b),
+            _ => panic!("Cannot compare sketches of different types."),
+        }
+    }
+
+    /// Merge sketches of the same type into a sketch of the union of their inputs.
+    ///
+    /// Returns `None` when the sketches have different types or there are none.
+    /// Panics when they can not be merged, as for the `merge_all` of each type.
+    pub fn merge_all<'a>(sketches: impl IntoIterator<Item = &'a Self>) -> Option<Self> {
+        let sketches = sketches.into_iter().collect::<Vec<_>>();
+        match sketches.first()? {
+            AnySketch::Bottom(_) => Some(AnySketch::Bottom(BottomSketch::merge_all(
+                sketches
+                    .iter()
+                    .map(|s| match s {
+                        AnySketch::Bottom(s) => Some(s),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?,
+            ))),
+            AnySketch::Bucket(_) => Some(AnySketch::Bucket(BucketSketch::merge_all(
+                sketches
+                    .iter()
+                    .map(|s| match s {
+                        AnySketch::Bucket(s) => Some(s),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?,
+            ))),
+            AnySketch::Scaled(_) => Some(AnySketch::Scaled(ScaledSketch::merge_all(
+                sketches
+                    .iter()
+                    .map(|s| match s {
+                        AnySketch::Scaled(s) => Some(s),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?,
+            ))),
+        }
+    }
+}
+
+/// An object containing the sketch parameters.
+///
+/// Contains internal state to optimize the implementation when sketching multiple similar sequences.
+pub struct Sketcher<H: KmerHasher = NtHash> {
+    rc: bool,
+    k: usize,
+    s: usize,
+    b: usize,
+    pub filter_empty: bool,
+    /// Fill empty buckets of [`BucketSketch`]es using optimal densification.
+    pub densify: bool,
+    /// Skip k-mers containing characters other than `ACGTacgt`.
+    /// Only has an effect for ASCII input, since packed input can only contain `ACGT`.
+    pub skip_non_acgt: bool,
+    /// Only sketch k-mers that occur at least `min_count` times in the input, as for `mash sketch -m`.
+    /// Use this for raw reads, where singleton k-mers are mostly sequencing errors.
+    /// Defaults to 1, which sketches all k-mers.
+    ///
+    /// Occurrences are counted in a count-min sketch of fixed size (8 MiB) as in [`SketchBuilder`],
+    /// so that memory does not grow with the coverage.
+    /// Counts can be overestimated, so a few k-mers occurring fewer times may be sketched as well.
+    pub min_count: usize,
+    /// Store the number of occurrences of each hash in bottom and scaled sketches,
+    /// as used by [`BottomSketch::weighted_similarity`] and [`BottomSketch::weighted_containment`].
+    /// Bottom sketches must then store full hashes (see [`Sketcher::b_bit_bottom`]).
+    /// Bucket sketches never store abundances.
+    pub track_abundance: bool,
+    /// Store only the low `b` bits of the `s` smallest hashes in bottom sketches, instead of the full hashes.
+    /// Defaults to false, in which case `b` only applies to bucket sketches.
+    ///
+    /// The similarity of b-bit bottom sketches is estimated from all pairs of equal values,
+    /// which is noisier than for full hashes. Requires `b >= 16`.
+    pub b_bit_bottom: bool,
+    /// The width of the k-mer hashes: 32 (default) or 64.
+    /// Use 64-bit hashes for inputs approaching 1Gbp and larger.
+    pub hash_bits: usize,
+    /// The function used to hash k-mers. See [`KmerHasher`].
+    pub hasher: H,
+    /// A seed to remix all hashes with, as with [`Seeded`].
+    /// Sketches with different seeds are independent replicates, and can not be compared with each other.
+    /// Defaults to 0, which does not change the hashes.
+    pub seed: u64,
+    /// The maximum number of inputs processed at once by [`Sketcher::sketch_many`] and the `_files` methods,
+    /// which bounds the number of inputs held in memory.
+    /// Defaults to 0, which uses the number of threads of the current rayon thread pool.
+    pub max_in_flight: usize,
+    /// Sequences with more than `chunk_len` k-mers are split into chunks of `chunk_len` k-mers,
+    /// overlapping by `k-1` characters, that are hashed in parallel on the current rayon thread pool.
+    /// The sketch does not depend on the chunk length or the number of threads.
+    /// Defaults to 2^22; use `usize::MAX` to hash each sequence on a single thread.
+    pub chunk_len: usize,
+
+    factor: AtomicUsize,
+}
+
+impl Sketcher {
+    /// Default sketcher that very fast at comparisons, but 20% slower at sketching.
+    /// Use for >= 50000 seqs, and safe default when input sequences are > 500'000 characters.
+    ///
+    /// When sequences are < 100'000 characters, inaccuracies may occur due to empty buckets.
+    pub fn default(k: usize) -> Self {
+        Sketcher {
+            rc: true,
+            k,
+            s: 32768,
+            b: 1,
+            filter_empty: false,
+            densify: false,
+            skip_non_acgt: false,
+            hash_bits: 32,
+            hasher: NtHash,
+            seed: 0,
+            min_count: 1,
+            track_abundance: false,
+            b_bit_bottom: false,
+            max_in_flight: 0,
+            chunk_len: 1 << 22,
+            factor: 2.into(),
+        }
+    }
+
+    /// Default sketcher that is fast at sketching, but somewhat slower at comparisons.
+    /// Use for <= 5000 seqs, or when input sequences are < 100'000 characters.
+    pub fn default_fast_sketching(k: usize) -> Self {
+        Sketcher {
+            rc: true,
+            k,
+            s: 8192,
+            b: 8,
+            filter_empty: false,
+            densify: false,
+            skip_non_acgt: false,
+            hash_bits: 32,
+            hasher: NtHash,
+            seed: 0,
+            min_count: 1,
+            track_abundance: false,
+            b_bit_bottom: false,
+            max_in_flight: 0,
+            chunk_len: 1 << 22,
+            factor: 2.into(),
+        }
+    }
+
+    /// Construct a new forward-only `Sketcher` object.
     pub fn new_fwd(k: usize, s: usize, b: usize) -> Self {
         Sketcher {
             rc: false,
@@ -303,6 +1804,16 @@ impl Sketcher {
             s,
             b,
             filter_empty: false,
+            densify: false,
+            skip_non_acgt: false,
+            hash_bits: 32,
+            hasher: NtHash,
+            seed: 0,
+            min_count: 1,
+            track_abundance: false,
+            b_bit_bottom: false,
+            max_in_flight: 0,
+            chunk_len: 1 << 22,
             factor: 2.into(),
         }
     }
@@ -315,168 +1826,707 @@ impl Sketcher {
             s,
             b,
             filter_empty: false,
+            densify: false,
+            skip_non_acgt: false,
+            hash_bits: 32,
+            hasher: NtHash,
+            seed: 0,
+            min_count: 1,
+            track_abundance: false,
+            b_bit_bottom: false,
+            max_in_flight: 0,
+            chunk_len: 1 << 22,
             factor: 2.into(),
         }
     }
 }
 
-impl Sketcher {
-    /// Return the `s` smallest `u32` k-mer hashes.
+impl Sketcher<Murmur3> {
+    /// Construct a `Sketcher` whose bottom sketches are compatible with [Mash](https://github.com/marbl/Mash):
+    /// canonical MurmurHash3 hashes with seed 42, 32-bit for `k <= 16` and 64-bit otherwise,
+    /// skipping k-mers with non-`ACGT` characters.
+    ///
+    /// MurmurHash3 is not computed using SIMD, so sketching is considerably slower.
+    pub fn new_mash(k: usize, s: usize) -> Self {
+        let hash_bits = if k <= 16 { 32 } else { 64 };
+        Sketcher {
+            rc: true,
+            k,
+            s,
+            b: hash_bits,
+            filter_empty: false,
+            densify: false,
+            skip_non_acgt: true,
+            hash_bits,
+            hasher: Murmur3,
+            seed: 0,
+            min_count: 1,
+            track_abundance: false,
+            b_bit_bottom: false,
+            max_in_flight: 0,
+            chunk_len: 1 << 22,
+            factor: 2.into(),
+        }
+    }
+
+    /// Construct a `Sketcher` whose bottom and scaled sketches are compatible with
+    /// [sourmash](https://sourmash.readthedocs.io/): canonical 64-bit MurmurHash3 hashes with seed 42,
+    /// skipping k-mers with non-`ACGT` characters.
+    ///
+    /// MurmurHash3 is not computed using SIMD, so sketching is considerably slower.
+    pub fn new_sourmash(k: usize, s: usize) -> Self {
+        Sketcher {
+            rc: true,
+            k,
+            s,
+            b: 64,
+            filter_empty: false,
+            densify: false,
+            skip_non_acgt: true,
+            hash_bits: 64,
+            hasher: Murmur3,
+            seed: 0,
+            min_count: 1,
+            track_abundance: false,
+            b_bit_bottom: false,
+            max_in_flight: 0,
+            chunk_len: 1 << 22,
+            factor: 2.into(),
+        }
+    }
+}
+
+impl<H: KmerHasher> Sketcher<H> {
+    /// Use `hasher` to hash k-mers instead.
+    pub fn with_hasher<H2: KmerHasher>(self, hasher: H2) -> Sketcher<H2> {
+        Sketcher {
+            rc: self.rc,
+            k: self.k,
+            s: self.s,
+            b: self.b,
+            filter_empty: self.filter_empty,
+            densify: self.densify,
+            skip_non_acgt: self.skip_non_acgt,
+            hash_bits: self.hash_bits,
+            hasher,
+            seed: self.seed,
+            min_count: self.min_count,
+            track_abundance: self.track_abundance,
+            b_bit_bottom: self.b_bit_bottom,
+            max_in_flight: self.max_in_flight,
+            chunk_len: self.chunk_len,
+            factor: self.factor,
+        }
+    }
+
+    /// Return the `s` smallest k-mer hashes.
     /// Prefer [`Sketcher::sketch`] instead, which is much faster and just as
     /// accurate when input sequences are not too short.
-    pub fn bottom_sketch<'s, S: Seq<'s>>(&self, seq: S) -> BottomSketch {
-        // Iterate all kmers and compute 32bit nthashes.
-        let n = seq.len();
+    pub fn bottom_sketch<'s, S: Seq<'s> + Send + Sync>(&self, seq: S) -> BottomSketch {
+        self.bottom_sketch_seqs(&[seq])
+    }
+
+    /// Return the `s` smallest k-mer hashes over a collection of sequences,
+    /// such as the records of a (draft) assembly.
+    /// Only k-mers fully inside a single sequence are hashed.
+    pub fn bottom_sketch_seqs<'s, S: Seq<'s> + Send + Sync>(&self, seqs: &[S]) -> BottomSketch {
+        match self.hash_bits {
+            32 => self.bottom_sketch_generic::<u32, S>(seqs),
+            64 => self.bottom_sketch_generic::<u64, S>(seqs),
+            _ => panic!("Unsupported hash width. Must be 32 or 64."),
+        }
+    }
+
+    fn bottom_sketch_generic<'s, T: HashValue, S: Seq<'s> + Send + Sync>(
+        &self,
+        seqs: &[S],
+    ) -> BottomSketch {
+        if self.min_count > 1 {
+            let AnySketch::Bottom(sketch) = self.build(self.bottom_sketch_builder(), seqs) else {
+                unreachable!()
+            };
+            return sketch;
+        }
+        // Iterate all kmers and compute 32bit or 64bit nthashes.
+        let seq_len = seqs.iter().map(|seq| seq.len()).sum::<usize>();
+        let n = seq_len.max(1);
         let mut out = vec![];
+        let mut abundances = vec![];
         loop {
-            let target = u32::MAX as usize / n * self.s;
-            let bound =
-                (target.saturating_mul(self.factor.load(SeqCst))).min(u32::MAX as usize) as u32;
+            let bound = self.bound::<T>(n);
 
-            self.collect_up_to_bound(seq, bound, &mut out);
+            self.collect_up_to_bound(seqs, bound, &mut out);
 
-            if bound == u32::MAX || out.len() >= self.s {
+            if bound == T::MAX || out.len() >= self.s {
                 out.sort_unstable();
-                out.dedup();
-                if bound == u32::MAX || out.len() >= self.s {
-                    out.resize(self.s, u32::MAX);
-
-                    break BottomSketch {
-                        rc: self.rc,
-                        k: self.k,
-                        b: self.b,
-                        bottom: out,
-                    };
+                let counts = self.track_abundance.then_some(&mut abundances);
+                dedup_counts(&mut out, counts);
+                if bound == T::MAX || out.len() >= self.s {
+                    let abundances = self.track_abundance.then_some(abundances);
+                    break self.bottom_sketch_from(out, abundances, seq_len as u64);
                 }
             }
             self.factor
-                .fetch_add((self.factor.load(SeqCst) + 1) / 2, SeqCst);
+                .fetch_add(self.factor.load(SeqCst).div_ceil(2), SeqCst);
             debug!("Increase factor to {}", self.factor.load(SeqCst));
         }
     }
 
+    /// Build a bottom sketch from the sorted distinct hashes, which include the `s` smallest ones,
+    /// and optionally their abundances.
+    fn bottom_sketch_from<T: HashValue>(
+        &self,
+        mut out: Vec<T>,
+        mut abundances: Option<Vec<u64>>,
+        seq_len: u64,
+    ) -> BottomSketch {
+        let b = if self.b_bit_bottom {
+            self.b.min(T::BITS)
+        } else {
+            T::BITS
+        };
+        assert!(
+            b >= 16,
+            "b-bit bottom sketches require b >= 16, since accidental matches dominate for smaller b."
+        );
+        assert!(
+            abundances.is_none() || b == T::BITS,
+            "Abundances can only be tracked for bottom sketches storing full hashes."
+        );
+        out.truncate(self.s);
+        let len = out.len();
+        if let Some(abundances) = &mut abundances {
+            abundances.truncate(len);
+        }
+        let kmers = bottom_kmers(&out, self.s);
+        if b == T::BITS {
+            out.resize(self.s, T::MAX);
+        } else {
+            // Keep only the low `b` bits, sorted by value.
+            let mask = u64::MAX >> (64 - b);
+            for x in &mut out {
+                *x = T::from_u64(x.to_u64() & mask);
+            }
+            out.sort_unstable();
+        }
+
+        BottomSketch {
+            rc: self.rc,
+            k: self.k,
+            s: self.s,
+            b,
+            hash_bits: T::BITS,
+            hasher: self.seeded_hasher().id(),
+            len,
+            kmers,
+            name: String::new(),
+            metadata: Metadata::default(),
+            seq_len,
+            min_count: self.min_count,
+            bottom: BitSketch::new(b, out),
+            abundances,
+        }
+    }
+
     /// s-buckets sketch. Splits the hashes into `s` buckets and returns the smallest hash per bucket.
     /// Buckets are determined via the remainder mod `s`.
-    pub fn sketch<'s, S: Seq<'s>>(&self, seq: S) -> BucketSketch {
-        // Iterate all kmers and compute 32bit nthashes.
-        let n = seq.len();
+    pub fn sketch<'s, S: Seq<'s> + Send + Sync>(&self, seq: S) -> BucketSketch {
+        self.sketch_seqs(&[seq])
+    }
+
+    /// s-buckets sketch over a collection of sequences, such as the records of a (draft) assembly.
+    /// Only k-mers fully inside a single sequence are hashed.
+    pub fn sketch_seqs<'s, S: Seq<'s> + Send + Sync>(&self, seqs: &[S]) -> BucketSketch {
+        match self.hash_bits {
+            32 => self.sketch_generic::<u32, S>(seqs),
+            64 => self.sketch_generic::<u64, S>(seqs),
+            _ => panic!("Unsupported hash width. Must be 32 or 64."),
+        }
+    }
+
+    fn sketch_generic<'s, T: HashValue, S: Seq<'s> + Send + Sync>(
+        &self,
+        seqs: &[S],
+    ) -> BucketSketch {
+        if self.min_count > 1 {
+            let AnySketch::Bucket(sketch) = self.build(self.sketch_builder(), seqs) else {
+                unreachable!()
+            };
+            return sketch;
+        }
+        // Iterate all kmers and compute 32bit or 64bit nthashes.
+        let seq_len = seqs.iter().map(|seq| seq.len()).sum::<usize>();
+        let n = seq_len.max(1);
         let mut out = vec![];
-        let mut buckets = vec![u32::MAX; self.s];
+        let mut buckets = vec![T::MAX; self.s];
         loop {
-            let target = u32::MAX as usize / n * self.s;
-            let bound =
-                (target.saturating_mul(self.factor.load(SeqCst))).min(u32::MAX as usize) as u32;
+            let bound = self.bound::<T>(n);
 
-            self.collect_up_to_bound(seq, bound, &mut out);
+            self.collect_up_to_bound(seqs, bound, &mut out);
 
-            if bound == u32::MAX || out.len() >= self.s {
-                let m = FM32::new(self.s as u32);
+            if bound == T::MAX || out.len() >= self.s {
+                let m = T::FastMod::new(self.s);
                 for &hash in &out {
                     let bucket = m.fastmod(hash);
                     buckets[bucket] = buckets[bucket].min(hash);
                 }
-                let mut empty = 0;
-                for &x in &buckets {
-                    if x == u32::MAX {
-                        empty += 1;
-                    }
-                }
-                if bound == u32::MAX || empty == 0 {
-                    if empty > 0 {
-                        info!("Found {empty} empty buckets.");
-                    }
-                    let empty = if empty > 0 && self.filter_empty {
-                        info!("Found {empty} empty buckets. Storing bitmask.");
-                        assert_eq!(buckets.len() % 64, 0);
-                        buckets
-                            .chunks_exact(64)
-                            .map(|xs| {
-                                xs.iter().enumerate().fold(0u64, |bits, (i, x)| {
-                                    bits | (((*x == u32::MAX) as u64) << i)
-                                })
-                            })
-                            .collect()
-                    } else {
-                        vec![]
-                    };
-
-                    break BucketSketch {
-                        rc: self.rc,
-                        k: self.k,
-                        b: self.b,
-                        empty,
-                        buckets: BitSketch::new(
-                            self.b,
-                            buckets.into_iter().map(|x| m.fastdiv(x) as u32).collect(),
-                        ),
-                    };
+                let empty = buckets.iter().filter(|&&x| x == T::MAX).count();
+                if bound == T::MAX || empty == 0 {
+                    break self.bucket_sketch_from(buckets, seq_len as u64);
                 }
             }
             self.factor
-                .fetch_add((self.factor.load(SeqCst) + 1) / 2, SeqCst);
+                .fetch_add(self.factor.load(SeqCst).div_ceil(2), SeqCst);
             debug!("Increase factor to {}", self.factor.load(SeqCst));
         }
     }
-    fn collect_up_to_bound<'s, S: Seq<'s>>(&self, seq: S, bound: u32, out: &mut Vec<u32>) {
+
+    /// Build a bucket sketch from the smallest hash in each bucket, with `T::MAX` for empty buckets.
+    fn bucket_sketch_from<T: HashValue>(&self, mut buckets: Vec<T>, seq_len: u64) -> BucketSketch {
+        let m = T::FastMod::new(self.s);
+        let mut empty = buckets.iter().filter(|&&x| x == T::MAX).count();
+        if empty > 0 {
+            info!("Found {empty} empty buckets.");
+        }
+        let kmers = bucket_kmers(&buckets.iter().map(|&x| m.fastdiv(x)).collect::<Vec<_>>());
+        let densified = self.densify;
+        if densified && empty > 0 && empty < self.s {
+            densify(&mut buckets);
+            empty = 0;
+        }
+        let empty = if empty > 0 && self.filter_empty {
+            info!("Found {empty} empty buckets. Storing bitmask.");
+            assert_eq!(buckets.len() % 64, 0);
+            buckets
+                .chunks_exact(64)
+                .map(|xs| {
+                    xs.iter()
+                        .enumerate()
+                        .fold(0u64, |bits, (i, x)| bits | (((*x == T::MAX) as u64) << i))
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        BucketSketch {
+            rc: self.rc,
+            k: self.k,
+            b: self.b,
+            hash_bits: T::BITS,
+            hasher: self.seeded_hasher().id(),
+            densified,
+            kmers,
+            name: String::new(),
+            metadata: Metadata::default(),
+            seq_len,
+            min_count: self.min_count,
+            empty,
+            buckets: BitSketch::new(self.b, buckets.into_iter().map(|x| m.fastdiv(x)).collect()),
+        }
+    }
+
+    /// FracMinHash sketch. Returns all k-mer hashes up to `MAX / scale` (see [`ScaledSketch::max_hash`]).
+    ///
+    /// Only `k`, `rc`, and the hash settings of the `Sketcher` are used; `s` and `b` are ignored.
+    pub fn scaled_sketch<'s, S: Seq<'s> + Send + Sync>(&self, seq: S, scale: u64) -> ScaledSketch {
+        self.scaled_sketch_seqs(&[seq], scale)
+    }
+
+    /// FracMinHash sketch over a collection of sequences, such as the records of a (draft) assembly.
+    /// Only k-mers fully inside a single sequence are hashed.
+    pub fn scaled_sketch_seqs<'s, S: Seq<'s> + Send + Sync>(
+        &self,
+        seqs: &[S],
+        scale: u64,
+    ) -> ScaledSketch {
+        match self.hash_bits {
+            32 => self.scaled_sketch_generic::<u32, S>(seqs, scale),
+            64 => self.scaled_sketch_generic::<u64, S>(seqs, scale),
+            _ => panic!("Unsupported hash width. Must be 32 or 64."),
+        }
+    }
+
+    /// Apply `f` to all `inputs` in parallel on the current rayon thread pool,
+    /// and return the results in the order of the inputs.
+    ///
+    /// `f` typically reads an input into memory and sketches it. At most [`Sketcher::max_in_flight`]
+    /// inputs are processed at once. Errors can be reported by returning a `Result` from `f`.
+    /// The adaptive state of the `Sketcher` is shared between all threads.
+    pub fn sketch_many<I: Sync, T: Send>(
+        &self,
+        inputs: &[I],
+        f: impl Fn(&Self, &I) -> T + Sync,
+    ) -> Vec<T> {
+        let lanes = match self.max_in_flight {
+            0 => rayon::current_num_threads(),
+            n => n,
+        };
+        let next = AtomicUsize::new(0);
+        let mut results = (0..lanes.min(inputs.len()))
+            .map(|_| vec![])
+            .collect::<Vec<_>>();
+        let (next, f) = (&next, &f);
+        rayon::scope(|scope| {
+            // Each lane processes one input at a time.
+            for lane in &mut results {
+                scope.spawn(move |_| {
+                    loop {
+                        let i = next.fetch_add(1, SeqCst);
+                        if i >= inputs.len() {
+                            break;
+                        }
+                        lane.push((i, f(self, &inputs[i])));
+                    }
+                });
+            }
+        });
+        let mut results = results.into_iter().flatten().collect::<Vec<_>>();
+        results.sort_unstable_by_key(|&(i, _)| i);
+        results.into_iter().map(|(_, x)| x).collect()
+    }
+
+    fn scaled_sketch_generic<'s, T: HashValue, S: Seq<'s> + Send + Sync>(
+        &self,
+        seqs: &[S],
+        scale: u64,
+    ) -> ScaledSketch {
+        assert!(scale > 0, "Scale must be positive.");
+        if self.min_count > 1 {
+            let AnySketch::Scaled(sketch) = self.build(self.scaled_sketch_builder(scale), seqs)
+            else {
+                unreachable!()
+            };
+            return sketch;
+        }
+        let bound = max_hash_for_scale(T::BITS, scale);
+        let mut out = vec![];
+        // 64-bit collection only filters on the high half, so filter again exactly.
+        self.collect_up_to_bound(seqs, T::from_u64(bound), &mut out);
+        out.retain(|h| h.to_u64() <= bound);
+        out.sort_unstable();
+        let mut abundances = vec![];
+        let counts = self.track_abundance.then_some(&mut abundances);
+        dedup_counts(&mut out, counts);
+        let abundances = self.track_abundance.then_some(abundances);
+        let seq_len = seqs.iter().map(|seq| seq.len() as u64).sum();
+        self.scaled_sketch_from(out, abundances, scale, seq_len)
+    }
+
+    /// Build a FracMinHash sketch from the sorted distinct hashes up to `MAX / scale`,
+    /// and optionally their abundances.
+    fn scaled_sketch_from<T: HashValue>(
+        &self,
+        out: Vec<T>,
+        abundances: Option<Vec<u64>>,
+        scale: u64,
+        seq_len: u64,
+    ) -> ScaledSketch {
+        ScaledSketch {
+            rc: self.rc,
+            k: self.k,
+            hash_bits: T::BITS,
+            hasher: self.seeded_hasher().id(),
+            scale,
+            name: String::new(),
+            metadata: Metadata::default(),
+            seq_len,
+            min_count: self.min_count,
+            hashes: BitSketch::new(T::BITS, out),
+            abundances,
+        }
+    }
+
+    /// The bound up to which hashes are collected for a total input length `n`:
+    /// the current `factor` times the expected largest of the `s` smallest hashes.
+    fn bound<T: HashValue>(&self, n: usize) -> T {
+        let target = (T::MAX.to_u64() / n as u64).saturating_mul(self.s as u64);
+        let bound = target.saturating_mul(self.factor.load(SeqCst) as u64);
+        T::from_u64(bound.min(T::MAX.to_u64()))
+    }
+
+    /// Collect all hashes up to `bound` of k-mers in the given sequences into `out`.
+    fn collect_up_to_bound<'s, T: HashValue, S: Seq<'s> + Send + Sync>(
+        &self,
+        seqs: &[S],
+        bound: T,
+        out: &mut Vec<T>,
+    ) {
+        out.clear();
+        let hasher = self.seeded_hasher();
+        for &seq in seqs {
+            // Packed sequences can only contain ACGT.
+            if self.skip_non_acgt && S::BASES_PER_BYTE == 1 {
+                for range in acgt_runs(seq, self.k) {
+                    self.collect_seq_up_to_bound(&hasher, seq.slice(range), bound, out);
+                }
+            } else {
+                self.collect_seq_up_to_bound(&hasher, seq, bound, out);
+            }
+        }
+    }
+
+    fn collect_seq_up_to_bound<'s, T: HashValue, S: Seq<'s> + Send + Sync>(
+        &self,
+        hasher: &Seeded<H>,
+        seq: S,
+        bound: T,
+        out: &mut Vec<T>,
+    ) {
+        if seq.len() < self.k {
+            return;
+        }
+        let kmers = seq.len() - self.k + 1;
+        if kmers <= self.chunk_len {
+            self.collect_chunk_up_to_bound(hasher, seq, bound, out);
+            return;
+        }
+        let outs = kmer_chunks(seq.len(), self.k, self.chunk_len)
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|range| {
+                let mut out = vec![];
+                self.collect_chunk_up_to_bound(hasher, seq.slice(range), bound, &mut out);
+                out
+            })
+            .collect::<Vec<_>>();
+        for chunk_out in outs {
+            out.extend_from_slice(&chunk_out);
+        }
+    }
+
+    fn collect_chunk_up_to_bound<'s, T: HashValue, S: Seq<'s>>(
+        &self,
+        hasher: &Seeded<H>,
+        seq: S,
+        bound: T,
+        out: &mut Vec<T>,
+    ) {
         if self.rc {
-            collect_up_to_bound_generic::<true, S>(seq, self.k, bound, out);
+            T::collect_up_to_bound::<true, S, _>(hasher, seq, self.k, bound, out);
         } else {
-            collect_up_to_bound_generic::<false, S>(seq, self.k, bound, out);
+            T::collect_up_to_bound::<false, S, _>(hasher, seq, self.k, bound, out);
         }
     }
+
+    /// The hasher remixed with [`Sketcher::seed`].
+    fn seeded_hasher(&self) -> Seeded<H> {
+        Seeded::new(self.hasher.clone(), self.seed)
+    }
 }
 
-fn collect_up_to_bound_generic<'s, const RC: bool, S: Seq<'s>>(
-    seq: S,
+/// Split a sequence of length `len >= k` into chunks of `chunk_len` k-mers, overlapping by `k-1` characters.
+///
+/// Chunks start at multiples of 4, so that chunks of packed sequences start at a byte boundary.
+fn kmer_chunks(
+    len: usize,
     k: usize,
-    bound: u32,
-    out: &mut Vec<u32>,
-) {
-    let simd_bound = u32x8::splat(bound);
-
-    let (hashes_head, hashes_tail) =
-        simd_minimizers::private::nthash::nthash_seq_simd::<RC, S, NtHasher>(seq, k, 1);
+    chunk_len: usize,
+) -> impl Iterator<Item = std::ops::Range<usize>> {
+    let kmers = len - k + 1;
+    let chunk_len = chunk_len.max(1).next_multiple_of(4);
+    (0..kmers)
+        .step_by(chunk_len)
+        .map(move |start| start..(start + chunk_len).min(kmers) + k - 1)
+}
 
-    out.clear();
-    let mut write_idx = 0;
-    for hashes in hashes_head {
-        let mask = hashes.cmp_lt(simd_bound);
-        if write_idx + 8 >= out.len() {
-            out.resize(write_idx * 3 / 2 + 8, 0);
+/// The ranges of all maximal runs of `ACGTacgt` characters of length at least `k`.
+fn acgt_runs<'s, S: Seq<'s>>(seq: S, k: usize) -> Vec<std::ops::Range<usize>> {
+    let mut runs = vec![];
+    let mut start = 0;
+    for i in 0..seq.len() {
+        if !matches!(
+            seq.get_ascii(i),
+            b'A' | b'C' | b'G' | b'T' | b'a' | b'c' | b'g' | b't'
+        ) {
+            if i - start >= k {
+                runs.push(start..i);
+            }
+            start = i + 1;
         }
-        unsafe { intrinsics::append_from_mask(hashes, mask, out, &mut write_idx) };
     }
+    if seq.len() - start >= k {
+        runs.push(start..seq.len());
+    }
+    runs
+}
+
+/// Sum `min(a_x, b_x)` and `max(a_x, b_x)` over the first `limit` hashes `x` in the union of the sorted `a` and `b`,
+/// with abundances `wa` and `wb`, and abundance 0 for absent hashes.
+fn weighted_union<T: Ord>(a: &[T], wa: &[u64], b: &[T], wb: &[u64], limit: usize) -> (u64, u64) {
+    let (mut min, mut max) = (0, 0);
+    let mut i = 0;
+    let mut j = 0;
+    let mut n = 0;
+    while n < limit && (i < a.len() || j < b.len()) {
+        let take_a = j == b.len() || (i < a.len() && a[i] <= b[j]);
+        let take_b = i == a.len() || (j < b.len() && b[j] <= a[i]);
+        let x = if take_a { wa[i] } else { 0 };
+        let y = if take_b { wb[j] } else { 0 };
+        i += take_a as usize;
+        j += take_b as usize;
+        min += x.min(y);
+        max += x.max(y);
+        n += 1;
+    }
+    (min, max)
+}
 
-    out.resize(write_idx, 0);
+/// The total abundance of the hashes of the sorted `a` that are in the sorted `b`, and the total abundance of `a`.
+fn weighted_shared<T: Ord>(a: &[T], wa: &[u64], b: &[T]) -> (u64, u64) {
+    let mut shared = 0;
+    let mut j = 0;
+    for (x, &w) in std::iter::zip(a, wa) {
+        j += b[j..].partition_point(|y| y < x);
+        if b.get(j) == Some(x) {
+            shared += w;
+        }
+    }
+    (shared, wa[..a.len()].iter().sum())
+}
 
-    for hash in hashes_tail {
-        if hash <= bound {
-            out.push(hash);
+/// The summed abundances of the sorted hashes `merged` over all sketches,
+/// given as sorted hashes with their abundances. `None` when some sketch has no abundances.
+fn sum_abundances<'a, T: Ord + 'a>(
+    merged: &[T],
+    sketches: impl Iterator<Item = (&'a [T], Option<&'a [u64]>)>,
+) -> Option<Vec<u64>> {
+    let mut sums = vec![0; merged.len()];
+    for (hashes, abundances) in sketches {
+        let abundances = abundances?;
+        let mut j = 0;
+        for (x, sum) in std::iter::zip(merged, &mut sums) {
+            j += hashes[j..].partition_point(|y| y < x);
+            if hashes.get(j) == Some(x) {
+                *sum += abundances[j];
+            }
         }
     }
+    Some(sums)
 }
 
-/// FastMod32, using the low 32 bits of the hash.
-/// Taken from https://github.com/lemire/fastmod/blob/master/include/fastmod.h
-#[derive(Copy, Clone, Debug)]
-struct FM32 {
-    d: u64,
-    m: u64,
+/// Keep one copy of each hash in the sorted `hashes`.
+/// When `counts` is given, it is set to the number of occurrences of each hash.
+fn dedup_counts<T: HashValue>(hashes: &mut Vec<T>, counts: Option<&mut Vec<u64>>) {
+    let Some(counts) = counts else {
+        hashes.dedup();
+        return;
+    };
+    counts.clear();
+    let mut len = 0;
+    let mut start = 0;
+    while start < hashes.len() {
+        let hash = hashes[start];
+        let end = start + hashes[start..].partition_point(|&h| h == hash);
+        hashes[len] = hash;
+        len += 1;
+        counts.push((end - start) as u64);
+        start = end;
+    }
+    hashes.truncate(len);
 }
-impl FM32 {
-    fn new(d: u32) -> Self {
-        Self {
-            d: d as u64,
-            m: u64::MAX / d as u64 + 1,
+
+/// The at most `s` smallest distinct values in the union of the sorted `a` and `b`.
+fn union_bottom<T: Ord + Copy>(a: &[T], b: &[T], s: usize) -> Vec<T> {
+    let mut out = Vec::with_capacity(s);
+    let (mut i, mut j) = (0, 0);
+    while out.len() < s && (i < a.len() || j < b.len()) {
+        let x = if j == b.len() || (i < a.len() && a[i] <= b[j]) {
+            a[i]
+        } else {
+            b[j]
+        };
+        out.push(x);
+        while i < a.len() && a[i] == x {
+            i += 1;
+        }
+        while j < b.len() && b[j] == x {
+            j += 1;
         }
     }
-    fn fastmod(self, h: u32) -> usize {
-        let lowbits = self.m.wrapping_mul(h as u64);
-        ((lowbits as u128 * self.d as u128) >> 64) as usize
+    out
+}
+
+/// Estimate the number of distinct k-mers from the `s` smallest distinct hashes (or fewer, when the input is small).
+fn bottom_kmers<T: HashValue>(bottom: &[T], s: usize) -> u64 {
+    match bottom.last() {
+        Some(&max) if bottom.len() == s && max != T::MAX => {
+            ((s.max(2) - 1) as f64 * T::MAX.to_u64() as f64 / max.to_u64() as f64).round() as u64
+        }
+        _ => bottom.len() as u64,
     }
-    fn fastdiv(self, h: u32) -> usize {
-        ((self.m as u128 * h as u128) >> 64) as u32 as usize
+}
+
+/// Estimate the number of distinct k-mers from the bucket minima, stored as quotients by `s`.
+/// Uses that the average normalized minimum over `s` buckets is around `s / n`, counting empty buckets as 1.
+/// When there are fewer than 3 k-mers per bucket and some buckets are empty,
+/// linear counting on the number of empty buckets is more accurate and used instead.
+fn bucket_kmers<T: HashValue>(buckets: &[T]) -> u64 {
+    let s = buckets.len();
+    let q_max = T::MAX.to_u64() / s as u64;
+    let empty = buckets.iter().filter(|q| q.to_u64() >= q_max).count();
+    let s = s as f64;
+    if empty == s as usize {
+        return 0;
     }
+    let sum = buckets
+        .iter()
+        .map(|q| (q.to_u64() as f64 / q_max as f64).min(1.0))
+        .sum::<f64>();
+    let estimate = s * (s - 1.0) / sum;
+    let estimate = if empty > 0 && estimate < 3.0 * s {
+        s * (s / empty as f64).ln()
+    } else {
+        estimate
+    };
+    estimate.round() as u64
+}
+
+/// Fill empty (`T::MAX`) buckets using optimal densification (Shrivastava, ICML 2017).
+///
+/// Each empty bucket `i` probes the buckets `h(i, 1), h(i, 2), ...` and copies the
+/// value of the first one that was non-empty before densification.
+/// The probe sequence only depends on `i`, so that all sketches are densified consistently.
+fn densify<T: HashValue>(buckets: &mut [T]) {
+    let s = buckets.len();
+    let m = hash::FM32::new(s);
+    let filled: Vec<bool> = buckets.iter().map(|&x| x != T::MAX).collect();
+    for i in 0..s {
+        if filled[i] {
+            continue;
+        }
+        let mut attempt = 1u64;
+        let donor = loop {
+            let j = m.fastmod(mix64(((i as u64) << 32) | attempt) as u32);
+            if filled[j] {
+                break j;
+            }
+            attempt += 1;
+        };
+        buckets[i] = buckets[donor];
+    }
+}
+
+/// The splitmix64 finalizer.
+/// The largest hash kept by a FracMinHash sketch of `bits`-bit hashes with the given `scale`.
+/// This is `MAX / scale` computed in `f64`, as sourmash does, so that imported and native sketches agree.
+fn max_hash_for_scale(bits: usize, scale: u64) -> u64 {
+    let max = u64::MAX >> (64 - bits);
+    ((max as f64 / scale as f64) as u64).min(max)
+}
+
+fn mix64(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+fn bottom32(sketch: BottomSketch) -> Vec<u32> {
+    let BitSketch::B32(bottom) = sketch.bottom else {
+        panic!("Expected 32-bit hashes.");
+    };
+    bottom
 }
 
 #[cfg(test)]
@@ -490,14 +2540,14 @@ fn test() {
         let s = n - k + 1;
         let seq = packed_seq::PackedSeqVec::random(n);
         let sketcher = crate::Sketcher::new_fwd(k, s, b);
-        let bottom = sketcher.bottom_sketch(seq.as_slice()).bottom;
+        let bottom = bottom32(sketcher.bottom_sketch(seq.as_slice()));
         assert_eq!(bottom.len(), s);
         assert!(bottom.is_sorted());
 
         let s = s.min(10);
         let seq = packed_seq::PackedSeqVec::random(n);
         let sketcher = crate::Sketcher::new_fwd(k, s, b);
-        let bottom = sketcher.bottom_sketch(seq.as_slice()).bottom;
+        let bottom = bottom32(sketcher.bottom_sketch(seq.as_slice()));
         assert_eq!(bottom.len(), s);
         assert!(bottom.is_sorted());
     }
@@ -514,7 +2564,7 @@ fn rc() {
             for s in (0..10).map(|_| rand::random_range(0..n - k + 1)) {
                 let seq = packed_seq::AsciiSeqVec::random(n);
                 let sketcher = crate::Sketcher::new_rc(k, s, b);
-                let bottom = sketcher.bottom_sketch(seq.as_slice()).bottom;
+                let bottom = bottom32(sketcher.bottom_sketch(seq.as_slice()));
                 assert_eq!(bottom.len(), s);
                 assert!(bottom.is_sorted());
 
@@ -526,9 +2576,681 @@ fn rc() {
                         .collect::<Vec<_>>(),
                 );
 
-                let bottom_rc = sketcher.bottom_sketch(seq_rc.as_slice()).bottom;
+                let bottom_rc = bottom32(sketcher.bottom_sketch(seq_rc.as_slice()));
                 assert_eq!(bottom, bottom_rc);
             }
         }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn densify_short_seqs() {
+    use packed_seq::SeqVec;
+
+    let k = 21;
+    let s = 1024;
+    for b in [1, 8, 32] {
+        let mut sketcher = crate::Sketcher::new_rc(k, s, b);
+        sketcher.densify = true;
+        for n in [100, 500, 2000] {
+            let seq1 = packed_seq::PackedSeqVec::random(n);
+            let seq2 = packed_seq::PackedSeqVec::random(n);
+            let sketch1 = sketcher.sketch(seq1.as_slice());
+            let sketch2 = sketcher.sketch(seq2.as_slice());
+            assert!(sketch1.densified);
+            assert!(sketch1.empty.is_empty());
+            assert_eq!(sketch1.similarity(&sketcher.sketch(seq1.as_slice())), 1.0);
+            // Random sequences share no k-mers.
+            assert!(sketch1.similarity(&sketch2) < 0.5);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn multiple_seqs() {
+    use packed_seq::SeqVec;
+
+    let k = 21;
+    let s = 64;
+    let b = 32;
+    let sketcher = crate::Sketcher::new_rc(k, s, b);
+    let mut seqs = packed_seq::AsciiSeqVec::default();
+    let ranges = (0..10)
+        .map(|_| seqs.push_ascii(&packed_seq::AsciiSeqVec::random(30).seq))
+        .collect::<Vec<_>>();
+    let slices = ranges
+        .iter()
+        .map(|r| seqs.slice(r.clone()))
+        .collect::<Vec<_>>();
+
+    // With 100 k-mers in total, all of them end up in the sketch.
+    let bottom = bottom32(sketcher.bottom_sketch_seqs(&slices));
+    let mut expected = slices
+        .iter()
+        .flat_map(|seq| bottom32(sketcher.bottom_sketch(*seq)).into_iter().take(10))
+        .collect::<Vec<_>>();
+    expected.sort_unstable();
+    expected.dedup();
+    expected.truncate(s);
+    assert_eq!(bottom[..expected.len()], expected);
+    assert!(bottom[expected.len()..].iter().all(|&x| x == u32::MAX));
+}
+
+#[cfg(test)]
+#[test]
+fn merge() {
+    use packed_seq::SeqVec;
+
+    let k = 21;
+    for (hash_bits, s) in [(32, 64), (32, 1024), (64, 1024)] {
+        let mut sketcher = crate::Sketcher::new_rc(k, s, hash_bits);
+        sketcher.filter_empty = true;
+        sketcher.hash_bits = hash_bits;
+        for n in [100, 1000, 100000] {
+            let seqs = (0..3)
+                .map(|_| packed_seq::PackedSeqVec::random(n))
+                .collect::<Vec<_>>();
+            let slices = seqs.iter().map(|seq| seq.as_slice()).collect::<Vec<_>>();
+
+            let bottoms = slices
+                .iter()
+                .map(|seq| sketcher.bottom_sketch(*seq))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                crate::BottomSketch::merge_all(&bottoms),
+                sketcher.bottom_sketch_seqs(&slices)
+            );
+            assert_eq!(
+                bottoms[0].merge(&bottoms[1]),
+                sketcher.bottom_sketch_seqs(&slices[..2])
+            );
+
+            let buckets = slices
+                .iter()
+                .map(|seq| sketcher.sketch(*seq))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                crate::BucketSketch::merge_all(&buckets),
+                sketcher.sketch_seqs(&slices)
+            );
+        }
+    }
+
+    // Counts are added when known for all inputs, and the path is kept when it is the same.
+    let metadata = |path: &str, records, gc| Metadata {
+        path: path.to_string(),
+        records,
+        gc,
+    };
+    let a = metadata("a.fa", Some(2), Some(10));
+    assert_eq!(a.merge(&a), metadata("a.fa", Some(4), Some(20)));
+    assert_eq!(
+        a.merge(&metadata("b.fa", Some(1), None)),
+        metadata("", Some(3), None)
+    );
+    assert_eq!(a.gc_content(40), Some(0.25));
+}
+
+#[cfg(test)]
+#[test]
+fn skip_non_acgt() {
+    use packed_seq::{AsciiSeq, SeqVec};
+
+    let k = 21;
+    let s = 256;
+    let b = 32;
+    let mut sketcher = crate::Sketcher::new_rc(k, s, b);
+    sketcher.skip_non_acgt = true;
+
+    let parts = [200, 10, 30, 500].map(|n| packed_seq::AsciiSeqVec::random(n).seq);
+    let mut seq = vec![];
+    for part in &parts {
+        seq.extend_from_slice(part);
+        seq.extend_from_slice(b"NNNNRYNNNN-");
+    }
+    seq.make_ascii_lowercase();
+    let parts = parts.each_ref().map(|part| AsciiSeq(part));
+
+    // Only the recorded sequence length differs.
+    let mut bottom = sketcher.bottom_sketch(AsciiSeq(&seq));
+    assert_eq!(bottom.seq_len, seq.len() as u64);
+    bottom.seq_len = 740;
+    assert_eq!(bottom, sketcher.bottom_sketch_seqs(&parts));
+    let mut bucket = sketcher.sketch(AsciiSeq(&seq));
+    bucket.seq_len = 740;
+    assert_eq!(bucket, sketcher.sketch_seqs(&parts));
+}
+
+#[cfg(test)]
+#[test]
+fn hash64() {
+    use packed_seq::SeqVec;
+    use simd_minimizers::private::nthash::{NtHasher, nthash_seq_scalar};
+
+    let k = 31;
+    let s = 128;
+    for n in [40, 1000, 100000] {
+        let seq = packed_seq::PackedSeqVec::random(n);
+        let mut sketcher = crate::Sketcher::new_rc(k, s, 64);
+        sketcher.hash_bits = 64;
+
+        let mut expected = std::iter::zip(
+            nthash_seq_scalar::<true, NtHasher>(seq.as_slice(), k),
+            nthash_seq_scalar::<true, hash::NtHasherLo>(seq.as_slice(), k),
+        )
+        .map(|(hi, lo)| ((hi as u64) << 32) | lo as u64)
+        .collect::<Vec<_>>();
+        expected.sort_unstable();
+        expected.dedup();
+        expected.resize(s, u64::MAX);
+
+        let sketch = sketcher.bottom_sketch(seq.as_slice());
+        assert_eq!(sketch.bottom, BitSketch::B64(expected));
+        assert_eq!(sketch.similarity(&sketch), 1.0);
+
+        let other = packed_seq::PackedSeqVec::random(n);
+        for b in [1, 8, 32, 64] {
+            let mut sketcher = crate::Sketcher::new_rc(k, 1024, b);
+            sketcher.hash_bits = 64;
+            sketcher.densify = true;
+            let sketch = sketcher.sketch(seq.as_slice());
+            assert_eq!(sketch.similarity(&sketch), 1.0);
+            assert!(sketch.similarity(&sketcher.sketch(other.as_slice())) < 0.5);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn kmer_mix() {
+    use packed_seq::SeqVec;
+
+    let k = 16;
+    let s = 1024;
+    for n in [20, 1000, 100000] {
+        let seq = packed_seq::AsciiSeqVec::random(n);
+        let rc_seq = packed_seq::AsciiSeqVec::from_vec(
+            seq.seq
+                .iter()
+                .rev()
+                .map(|&c| packed_seq::complement_char(c))
+                .collect(),
+        );
+        for hash_bits in [32, 64] {
+            let mut sketcher = crate::Sketcher::new_rc(k, s, hash_bits);
+            sketcher.hash_bits = hash_bits;
+            let mut sketcher = sketcher.with_hasher(KmerMix);
+            let sketch = sketcher.bottom_sketch(seq.as_slice());
+            assert_eq!(sketch, sketcher.bottom_sketch(rc_seq.as_slice()));
+            assert_eq!(sketch.similarity(&sketch), 1.0);
+            sketcher.filter_empty = true;
+            assert_eq!(
+                sketcher.sketch(seq.as_slice()),
+                sketcher.sketch(rc_seq.as_slice())
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn b_bit_bottom() {
+    use packed_seq::SeqVec;
+
+    let k = 21;
+    let s = 4096;
+    let n = 200000;
+    // Two sequences sharing half their k-mers, for a Jaccard similarity of 1/3.
+    let parts = [0; 3].map(|_| packed_seq::AsciiSeqVec::random(n).seq);
+    let seq1 = packed_seq::AsciiSeqVec::from_vec([&parts[0][..], &parts[1][..]].concat());
+    let seq2 = packed_seq::AsciiSeqVec::from_vec([&parts[1][..], &parts[2][..]].concat());
+
+    for (b, hash_bits) in [(16, 32), (16, 64), (32, 64)] {
+        let mut sketcher = crate::Sketcher::new_rc(k, s, b);
+        sketcher.hash_bits = hash_bits;
+        let exact = sketcher
+            .bottom_sketch(seq1.as_slice())
+            .similarity(&sketcher.bottom_sketch(seq2.as_slice()));
+        assert!((exact - 1. / 3.).abs() < 0.03, "{exact}");
+
+        // Full hashes are stored unless b-bit bottom sketches are requested.
+        assert_eq!(sketcher.bottom_sketch(seq1.as_slice()).b(), hash_bits);
+        sketcher.b_bit_bottom = true;
+        let sketch1 = sketcher.bottom_sketch(seq1.as_slice());
+        let sketch2 = sketcher.bottom_sketch(seq2.as_slice());
+        assert_eq!(sketch1.b(), b);
+        assert_eq!(sketch1.len, s);
+        let sim = sketch1.similarity(&sketch1);
+        // The noise from accidental matches is about `2^(1 - b/2)`, independent of `s`.
+        assert!((sim - 1.0).abs() < 0.05, "b={b}: {sim}");
+        let sim = sketch1.similarity(&sketch2);
+        assert!((sim - exact).abs() < 0.04, "b={b}: {sim} vs {exact}");
+    }
+
+    // Short sequences with fewer than `s` distinct k-mers.
+    let short = packed_seq::AsciiSeqVec::random(100);
+    let mut sketcher = crate::Sketcher::new_rc(k, 128, 16);
+    sketcher.b_bit_bottom = true;
+    let sketch = sketcher.bottom_sketch(short.as_slice());
+    assert_eq!(sketch.len, 80);
+    let sim = sketch.similarity(&sketcher.bottom_sketch(seq1.as_slice()));
+    assert!((0.0..=1.0).contains(&sim));
+}
+
+#[cfg(test)]
+#[test]
+#[should_panic(expected = "require b >= 16")]
+fn b_bit_bottom_small_b() {
+    use packed_seq::SeqVec;
+    let mut sketcher = crate::Sketcher::new_rc(21, 128, 8);
+    sketcher.b_bit_bottom = true;
+    sketcher.bottom_sketch(packed_seq::PackedSeqVec::random(1000).as_slice());
+}
+
+#[cfg(test)]
+#[test]
+fn scaled() {
+    use packed_seq::SeqVec;
+
+    let k = 21;
+    for hash_bits in [32, 64] {
+        let mut sketcher = crate::Sketcher::new_rc(k, 1 << 17, hash_bits);
+        sketcher.hash_bits = hash_bits;
+        for n in [10, 1000, 100000] {
+            let seq = packed_seq::PackedSeqVec::random(n);
+            let bottom = sketcher.bottom_sketch(seq.as_slice());
+            for scale in [1, 10, 1000] {
+                let sketch = sketcher.scaled_sketch(seq.as_slice(), scale);
+                if hash_bits == 32 {
+                    assert_eq!(sketch.max_hash(), u32::MAX as u64 / scale);
+                }
+
+                // Exactly the bottom hashes up to the bound are kept.
+                let expected = match (&sketch.hashes, &bottom.bottom) {
+                    (BitSketch::B32(_), BitSketch::B32(h)) => BitSketch::B32(
+                        h[..bottom.len]
+                            .iter()
+                            .copied()
+                            .filter(|&h| h as u64 <= sketch.max_hash())
+                            .collect(),
+                    ),
+                    (BitSketch::B64(_), BitSketch::B64(h)) => BitSketch::B64(
+                        h[..bottom.len]
+                            .iter()
+                            .copied()
+                            .filter(|&h| h <= sketch.max_hash())
+                            .collect(),
+                    ),
+                    _ => panic!(),
+                };
+                assert_eq!(sketch.hashes, expected);
+                assert_eq!(sketch.similarity(&sketch), 1.0);
+                assert_eq!(
+                    sketch.downsample(1000),
+                    sketcher.scaled_sketch(seq.as_slice(), 1000)
+                );
+            }
+
+            let other = packed_seq::PackedSeqVec::random(n);
+            let sketches = [&seq, &other].map(|seq| sketcher.scaled_sketch(seq.as_slice(), 10));
+            let both = sketcher.scaled_sketch_seqs(&[seq.as_slice(), other.as_slice()], 10);
+            assert_eq!(sketches[0].merge(&sketches[1]), both);
+            assert_eq!(
+                sketches[0].merge(&sketcher.scaled_sketch(other.as_slice(), 100)),
+                both.downsample(100)
+            );
+            if n >= 1000 {
+                assert!(sketches[0].similarity(&sketches[1]) < 0.1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn containment() {
+    use packed_seq::SeqVec;
+
+    let k = 21;
+    let n = 100000;
+    // `small` is fully contained in `large`, which is 4 times larger.
+    let parts = [0; 4].map(|_| packed_seq::AsciiSeqVec::random(n).seq);
+    let small = packed_seq::AsciiSeqVec::from_vec(parts[0].clone());
+    let large = packed_seq::AsciiSeqVec::from_vec(parts.concat());
+
+    let check = |ab: Containment, ba: Containment, max: Containment, eps: f32| {
+        assert!((ab.containment - 1.0).abs() < eps, "{ab:?}");
+        assert!((ba.containment - 0.25).abs() < eps, "{ba:?}");
+        assert_eq!(max, ab);
+        assert!(ab.shared <= ab.total);
+    };
+
+    for b in [16, 32] {
+        let mut sketcher = crate::Sketcher::new_rc(k, 4096, b);
+        sketcher.b_bit_bottom = true;
+        let a = sketcher.bottom_sketch(small.as_slice());
+        let b = sketcher.bottom_sketch(large.as_slice());
+        assert!((a.kmers as f32 / n as f32 - 1.0).abs() < 0.1, "{}", a.kmers);
+        assert!(
+            (b.kmers as f32 / (4 * n) as f32 - 1.0).abs() < 0.1,
+            "{}",
+            b.kmers
+        );
+        check(
+            a.containment(&b),
+            b.containment(&a),
+            a.max_containment(&b),
+            0.1,
+        );
+    }
+
+    for b in [8, 32] {
+        let sketcher = crate::Sketcher::new_rc(k, 4096, b);
+        let a = sketcher.sketch(small.as_slice());
+        let b = sketcher.sketch(large.as_slice());
+        assert!((a.kmers as f32 / n as f32 - 1.0).abs() < 0.1, "{}", a.kmers);
+        assert!(
+            (b.kmers as f32 / (4 * n) as f32 - 1.0).abs() < 0.1,
+            "{}",
+            b.kmers
+        );
+        check(
+            a.containment(&b),
+            b.containment(&a),
+            a.max_containment(&b),
+            0.1,
+        );
+    }
+
+    let sketcher = crate::Sketcher::new_rc(k, 0, 32);
+    let a = sketcher.scaled_sketch(small.as_slice(), 100);
+    let b = sketcher.scaled_sketch(large.as_slice(), 100);
+    let ab = a.containment(&b);
+    assert_eq!(ab.containment, 1.0);
+    assert_eq!(ab.total, a.len());
+    check(ab, b.containment(&a), a.max_containment(&b), 0.1);
+}
+
+#[cfg(test)]
+#[test]
+fn distance() {
+    use packed_seq::SeqVec;
+
+    let k = 21;
+    let n = 100000;
+    let seq1 = packed_seq::PackedSeqVec::random(n);
+    let seq2 = packed_seq::PackedSeqVec::random(n);
+    for b in [16, 32] {
+        let mut sketcher = crate::Sketcher::new_rc(k, 2048, b);
+        sketcher.b_bit_bottom = true;
+        let bottom = [&seq1, &seq2].map(|seq| sketcher.bottom_sketch(seq.as_slice()));
+        let bucket = [&seq1, &seq2].map(|seq| sketcher.sketch(seq.as_slice()));
+        for d in [
+            bottom[0].distance(&bottom[0]),
+            bucket[0].distance(&bucket[0]),
+        ] {
+            assert!(d.mash_distance < 0.01, "{d:?}");
+            assert!(d.p_value < 1e-100, "{d:?}");
+        }
+        // Random sequences share no k-mers, up to b-bit noise.
+        for d in [
+            bottom[0].distance(&bottom[1]),
+            bucket[0].distance(&bucket[1]),
+        ] {
+            assert!(d.mash_distance > 0.15, "{d:?}");
+            assert!(d.ani < 0.85, "{d:?}");
+        }
+    }
+
+    // As in Mash, the p-value of bottom sketches is based on the hashes in the union of both sketches.
+    let sketcher = crate::Sketcher::new_rc(k, 2048, 32);
+    let short =
+        [100, 200].map(|n| sketcher.bottom_sketch(packed_seq::PackedSeqVec::random(n).as_slice()));
+    let d = short[0].distance(&short[1]);
+    assert_eq!(d.total, short[0].len + short[1].len);
+    assert_eq!(d.shared, 0);
+}
+
+#[cfg(test)]
+#[test]
+#[should_panic(expected = "different sizes")]
+fn merge_different_s() {
+    use packed_seq::SeqVec;
+    let seq = packed_seq::PackedSeqVec::random(1000);
+    let a = Sketcher::new_rc(15, 100, 32).bottom_sketch(seq.as_slice());
+    let b = Sketcher::new_rc(15, 200, 32).bottom_sketch(seq.as_slice());
+    a.merge(&b);
+}
+
+#[cfg(test)]
+#[test]
+#[should_panic(expected = "different hash functions")]
+fn different_hashers() {
+    use packed_seq::SeqVec;
+    let seq = packed_seq::PackedSeqVec::random(1000);
+    let sketcher = Sketcher::new_rc(15, 100, 32);
+    let a = sketcher.bottom_sketch(seq.as_slice());
+    let b = sketcher
+        .with_hasher(Seeded::new(NtHash, 1))
+        .bottom_sketch(seq.as_slice());
+    a.similarity(&b);
+}
+
+#[cfg(test)]
+#[test]
+#[should_panic(expected = "different hash functions")]
+fn scaled_set_sizes_different_hashers() {
+    use packed_seq::SeqVec;
+    let seq = packed_seq::PackedSeqVec::random(1000);
+    let sketcher = Sketcher::new_rc(15, 100, 32);
+    let a = sketcher.scaled_sketch(seq.as_slice(), 1);
+    let b = sketcher
+        .with_hasher(Seeded::new(NtHash, 1))
+        .scaled_sketch(seq.as_slice(), 1);
+    a.set_sizes(&b);
+}
+
+#[cfg(test)]
+#[test]
+fn seeds() {
+    use packed_seq::SeqVec;
+
+    // `a` and `b` share half their k-mers, so their Jaccard similarity is 1/3.
+    let n = 50000;
+    let parts = [0; 3].map(|_| packed_seq::AsciiSeqVec::random(n).seq);
+    let a = packed_seq::AsciiSeqVec::from_vec([&parts[0][..], &parts[1][..]].concat());
+    let b = packed_seq::AsciiSeqVec::from_vec([&parts[0][..], &parts[2][..]].concat());
+
+    let mut sketcher = Sketcher::new_rc(21, 2048, 32);
+    let mut scaled = vec![];
+    for seed in 0..8 {
+        sketcher.seed = seed;
+        let sa = sketcher.bottom_sketch(a.as_slice());
+        let sb = sketcher.bottom_sketch(b.as_slice());
+        assert_eq!(sa.hasher().seed, seed);
+        let j = sa.similarity(&sb);
+        assert!((j - 1.0 / 3.0).abs() < 0.05, "seed {seed}: {j}");
+        let j = sketcher
+            .sketch(a.as_slice())
+            .similarity(&sketcher.sketch(b.as_slice()));
+        assert!((j - 1.0 / 3.0).abs() < 0.05, "seed {seed}: {j}");
+        let sa = sketcher.scaled_sketch(a.as_slice(), 50);
+        assert_eq!(sa.hasher().seed, seed);
+        scaled.push(sa);
+    }
+    // Replicates with different seeds are different.
+    scaled.dedup_by(|x, y| x.hashes == y.hashes);
+    assert_eq!(scaled.len(), 8);
+}
+
+#[cfg(test)]
+#[test]
+fn threads() {
+    use packed_seq::SeqVec;
+
+    let packed = packed_seq::PackedSeqVec::random(100000);
+    let mut ascii = packed_seq::AsciiSeqVec::random(100000);
+    ascii.seq[23456..23500].fill(b'N');
+    for (rc, hash_bits) in [(true, 32), (false, 32), (true, 64)] {
+        let sketcher = |chunk_len| {
+            let mut sketcher = Sketcher::new_rc(21, 1024, 32);
+            sketcher.rc = rc;
+            sketcher.hash_bits = hash_bits;
+            sketcher.skip_non_acgt = true;
+            sketcher.filter_empty = true;
+            sketcher.chunk_len = chunk_len;
+            sketcher
+        };
+        let single = sketcher(usize::MAX);
+        let expected = (
+            single.bottom_sketch(packed.as_slice()),
+            single.sketch(packed.as_slice()),
+            single.scaled_sketch(ascii.as_slice(), 100),
+            single.sketch(ascii.as_slice()),
+        );
+        // The sketch does not depend on the chunk length or the number of threads.
+        for chunk_len in [5, 1001, 30000] {
+            for threads in [1, 3, 8] {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap();
+                let sketcher = sketcher(chunk_len);
+                let sketches = pool.install(|| {
+                    (
+                        sketcher.bottom_sketch(packed.as_slice()),
+                        sketcher.sketch(packed.as_slice()),
+                        sketcher.scaled_sketch(ascii.as_slice(), 100),
+                        sketcher.sketch(ascii.as_slice()),
+                    )
+                });
+                assert_eq!(
+                    sketches, expected,
+                    "chunk_len={chunk_len} threads={threads}"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn min_count() {
+    use packed_seq::SeqVec;
+
+    let a = packed_seq::PackedSeqVec::random(5000);
+    let b = packed_seq::PackedSeqVec::random(5000);
+    let (a, b) = (a.as_slice(), b.as_slice());
+    for hash_bits in [32, 64] {
+        let mut sketcher = Sketcher::new_rc(21, 256, hash_bits);
+        sketcher.hash_bits = hash_bits;
+        sketcher.filter_empty = true;
+        let bottom = sketcher.bottom_sketch(a);
+        let bucket = sketcher.sketch(a);
+        let scaled = sketcher.scaled_sketch(a, 10);
+
+        // Only the k-mers of `a` occur at least twice.
+        sketcher.min_count = 2;
+        let seqs = [a, b, a];
+        let bottom2 = sketcher.bottom_sketch_seqs(&seqs);
+        assert_eq!(bottom2.bottom, bottom.bottom);
+        assert_eq!(bottom2.min_count(), 2);
+        assert_eq!(sketcher.sketch_seqs(&seqs).buckets, bucket.buckets);
+        assert_eq!(sketcher.scaled_sketch_seqs(&seqs, 10).hashes, scaled.hashes);
+        sketcher.min_count = 3;
+        assert_eq!(sketcher.scaled_sketch_seqs(&seqs, 10).len(), 0);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn abundance() {
+    use packed_seq::SeqVec;
+
+    let a = packed_seq::PackedSeqVec::random(5000);
+    let b = packed_seq::PackedSeqVec::random(5000);
+    let (a, b) = (a.as_slice(), b.as_slice());
+    for hash_bits in [32, 64] {
+        let mut sketcher = Sketcher::new_rc(21, 256, hash_bits);
+        sketcher.hash_bits = hash_bits;
+        assert_eq!(sketcher.bottom_sketch(a).abundances(), None);
+        sketcher.track_abundance = true;
+        let bottom = sketcher.bottom_sketch(a);
+        let scaled = sketcher.scaled_sketch(a, 10);
+        assert!(bottom.abundances().unwrap().iter().all(|&x| x == 1));
+
+        // Every k-mer of `a` occurs twice.
+        let bottom2 = sketcher.bottom_sketch_seqs(&[a, a]);
+        let scaled2 = sketcher.scaled_sketch_seqs(&[a, a], 10);
+        assert!(bottom2.abundances().unwrap().iter().all(|&x| x == 2));
+        assert_eq!(bottom2.similarity(&bottom), 1.0);
+        assert_eq!(bottom2.weighted_similarity(&bottom), 0.5);
+        assert_eq!(scaled2.weighted_similarity(&scaled), 0.5);
+        assert_eq!(BottomSketch::merge_all([&bottom, &bottom]), bottom2);
+        assert_eq!(scaled.merge(&scaled), scaled2);
+
+        // Two thirds of the k-mer occurrences of `a,a,b` are in `a`.
+        let bottom3 = sketcher.bottom_sketch_seqs(&[a, b, a]);
+        let scaled3 = sketcher.scaled_sketch_seqs(&[a, b, a], 10);
+        assert_eq!(bottom.weighted_containment(&bottom3), 1.0);
+        assert_eq!(scaled.weighted_containment(&scaled3), 1.0);
+        assert!((bottom3.weighted_containment(&bottom) - 2. / 3.).abs() < 0.1);
+        assert!((scaled3.weighted_containment(&scaled) - 2. / 3.).abs() < 0.1);
+        assert!((bottom3.weighted_similarity(&bottom) - 1. / 3.).abs() < 0.1);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn set_sizes() {
+    use packed_seq::SeqVec;
+
+    let n = 20000;
+    let [a, b, c] = [(); 3].map(|_| packed_seq::PackedSeqVec::random(n));
+    let x = [a.as_slice(), b.as_slice()];
+    let y = [b.as_slice(), c.as_slice()];
+    let close =
+        |estimate: u64, expected: usize| (estimate as f64 / expected as f64 - 1.0).abs() < 0.15;
+    for (b, hash_bits) in [(32, 32), (64, 64), (16, 32), (16, 64)] {
+        let mut sketcher = Sketcher::new_rc(21, 4096, b);
+        sketcher.hash_bits = hash_bits;
+        sketcher.filter_empty = true;
+        sketcher.b_bit_bottom = true;
+        let sketches = [
+            [
+                sketcher.bottom_sketch_seqs(&x),
+                sketcher.bottom_sketch_seqs(&y),
+            ]
+            .map(AnySketch::from),
+            [sketcher.sketch_seqs(&x), sketcher.sketch_seqs(&y)].map(AnySketch::from),
+            [
+                sketcher.scaled_sketch_seqs(&x, 10),
+                sketcher.scaled_sketch_seqs(&y, 10),
+            ]
+            .map(AnySketch::from),
+        ];
+        for [x, y] in &sketches {
+            let info = format!("{} b={b} hash_bits={hash_bits}", x.kind());
+            assert!(close(x.cardinality(), 2 * n), "{info} {}", x.cardinality());
+            let sizes = x.set_sizes(y);
+            assert!(close(sizes.union, 3 * n), "{info} {sizes:?}");
+            assert!(close(sizes.intersection, n), "{info} {sizes:?}");
+            assert_eq!(sizes, y.set_sizes(x), "{info}");
+        }
+    }
+
+    // Small inputs are counted exactly by bottom sketches storing full hashes.
+    let sketcher = Sketcher::new_rc(21, 1024, 32);
+    let x = sketcher.bottom_sketch(a.slice(0..300));
+    let y = sketcher.bottom_sketch(a.slice(200..500));
+    assert_eq!(x.cardinality(), 280);
+    assert_eq!(
+        x.set_sizes(&y),
+        SetSizes {
+            union: 480,
+            intersection: 80
+        }
+    );
+}
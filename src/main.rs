@@ -0,0 +1,652 @@
+//! The `simd-sketch` command line tool.
+//!
+//! Sketches are stored in the binary format of [`simd_sketch::serialize`].
+//! A sketch file contains any number of sketches, one after the other.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use packed_seq::AsciiSeq;
+use rayon::prelude::*;
+use simd_sketch::{
+    AnySketch, BottomSketch, BucketSketch, KmerHasher, KmerMix, Metadata, Murmur3, NtHash,
+    SimilarityMatrix, Sketcher, mash_distance, with_fastx_records,
+};
+use tracing::info;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Number of threads to use. Defaults to the number of cores.
+    #[arg(short = 't', long, global = true)]
+    threads: Option<usize>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Sketch FASTA/FASTQ files (optionally compressed) into a sketch file.
+    Sketch(SketchArgs),
+    /// Compute distances between query and reference sketches, or between all pairs of sketches.
+    Dist(DistArgs),
+    /// Print a lower-triangular distance matrix in PHYLIP format.
+    Triangle(TriangleArgs),
+    /// Print the parameters of sketches.
+    Info(InfoArgs),
+    /// Merge sketches into a single sketch of the union of their inputs.
+    Merge(MergeArgs),
+}
+
+#[derive(Args)]
+struct SketchArgs {
+    /// FASTA/FASTQ files, or directories that are searched recursively for them.
+    inputs: Vec<PathBuf>,
+    /// Files containing one input path per line.
+    #[arg(short, long)]
+    list: Vec<PathBuf>,
+    /// The output sketch file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Sketch each record separately instead of each file.
+    #[arg(short, long)]
+    individual: bool,
+    /// The maximum number of files read into memory at once. Defaults to the number of threads.
+    #[arg(long)]
+    in_flight: Option<usize>,
+
+    /// The type of sketch.
+    #[arg(long, value_enum, default_value_t = Kind::Bottom)]
+    kind: Kind,
+    /// k-mer length
+    #[arg(short, default_value_t = 31)]
+    k: usize,
+    /// Sketch size, for bottom and bucket sketches.
+    #[arg(short, default_value_t = 10000)]
+    s: usize,
+    /// Store bottom-b bits of each element of bucket sketches. Must be 1, 8, 16, 32, or 64.
+    #[arg(short, default_value_t = 32)]
+    b: usize,
+    /// Also store only the bottom-b bits of bottom sketches, instead of full hashes. Requires b >= 16.
+    #[arg(long)]
+    b_bit_bottom: bool,
+    /// Width of the k-mer hashes: 32 or 64.
+    #[arg(long, default_value_t = 32)]
+    hash_bits: usize,
+    /// Keep one in `scale` k-mers, for scaled sketches.
+    #[arg(long, default_value_t = 1000)]
+    scale: u64,
+    /// The k-mer hash function.
+    #[arg(long, value_enum, default_value_t = Hasher::NtHash)]
+    hasher: Hasher,
+    /// Seed for the k-mer hashes. Only sketches with equal seeds can be compared.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Hash forward k-mers only, instead of canonical k-mers.
+    #[arg(long)]
+    fwd: bool,
+    /// Fill empty buckets of bucket sketches using densification.
+    #[arg(long)]
+    densify: bool,
+    /// Only sketch k-mers occurring at least this many times, to filter sequencing errors in reads.
+    #[arg(short, long, default_value_t = 1)]
+    min_count: usize,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Kind {
+    Bottom,
+    Bucket,
+    Scaled,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Hasher {
+    #[value(name = "nthash")]
+    NtHash,
+    KmerMix,
+    Murmur3,
+}
+
+#[derive(Args)]
+struct DistArgs {
+    /// Reference sketch files.
+    #[arg(required = true)]
+    references: Vec<PathBuf>,
+    /// Query sketch files. When not given, all pairs of reference sketches are compared.
+    #[arg(short, long)]
+    query: Vec<PathBuf>,
+    /// Only print pairs with at most this Mash distance.
+    #[arg(short = 'd', long)]
+    max_distance: Option<f32>,
+    /// The output format.
+    #[arg(short, long, value_enum, default_value_t = Format::Tsv)]
+    format: Format,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Format {
+    /// Tab-separated `query reference jaccard distance ani p_value shared total`, with a header.
+    Tsv,
+    /// Tab-separated `reference query distance p_value shared/total`, as printed by `mash dist`.
+    Mash,
+}
+
+#[derive(Args)]
+struct TriangleArgs {
+    /// Sketch files.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    /// Sketch files.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+struct MergeArgs {
+    /// Sketch files.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// The output sketch file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// The name of the merged sketch. Defaults to the output path.
+    #[arg(short, long)]
+    name: Option<String>,
+}
+
+fn main() -> ExitCode {
+    init_trace();
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    if let Some(threads) = cli.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+    match cli.command {
+        Command::Sketch(args) => sketch(&args),
+        Command::Dist(args) => dist(&args),
+        Command::Triangle(args) => triangle(&args),
+        Command::Info(args) => print_info(&args),
+        Command::Merge(args) => merge(&args),
+    }
+}
+
+fn sketch(args: &SketchArgs) -> Result<()> {
+    if !matches!(args.hash_bits, 32 | 64) {
+        return Err(format!("hash-bits must be 32 or 64, not {}.", args.hash_bits).into());
+    }
+    if !matches!(args.b, 1 | 8 | 16 | 32 | 64) || args.b > args.hash_bits {
+        return Err(format!(
+            "b must be 1, 8, 16, 32, or 64, and at most hash-bits, not {}.",
+            args.b
+        )
+        .into());
+    }
+    if args.k == 0 || args.s == 0 || args.scale == 0 {
+        return Err("k, s, and scale must be positive.".into());
+    }
+    if args.b == 1 && !args.s.is_multiple_of(64) {
+        return Err("s must be a multiple of 64 when b=1.".into());
+    }
+    if matches!(args.hasher, Hasher::KmerMix) && args.k > 16 {
+        return Err("The k-mer mix hasher only supports k <= 16.".into());
+    }
+
+    let mut paths = vec![];
+    for list in &args.list {
+        let lines = BufReader::new(open(list)?).lines();
+        for line in lines {
+            let line = line?;
+            if !line.trim().is_empty() {
+                collect_paths(Path::new(line.trim()), &mut paths)?;
+            }
+        }
+    }
+    for input in &args.inputs {
+        collect_paths(input, &mut paths)?;
+    }
+    if paths.is_empty() {
+        return Err("No input files given.".into());
+    }
+    if args.b_bit_bottom && args.b < 16 {
+        return Err("--b-bit-bottom requires b >= 16.".into());
+    }
+
+    let mut sketcher = if args.fwd {
+        Sketcher::new_fwd(args.k, args.s, args.b)
+    } else {
+        Sketcher::new_rc(args.k, args.s, args.b)
+    };
+    sketcher.filter_empty = true;
+    sketcher.skip_non_acgt = true;
+    sketcher.densify = args.densify;
+    sketcher.b_bit_bottom = args.b_bit_bottom;
+    sketcher.hash_bits = args.hash_bits;
+    sketcher.seed = args.seed;
+    sketcher.min_count = args.min_count;
+    sketcher.max_in_flight = args.in_flight.unwrap_or(0);
+
+    let start = Instant::now();
+    let sketches = match args.hasher {
+        Hasher::NtHash => sketch_files(&sketcher, args, &paths)?,
+        Hasher::KmerMix => sketch_files(&sketcher.with_hasher(KmerMix), args, &paths)?,
+        Hasher::Murmur3 => sketch_files(&sketcher.with_hasher(Murmur3), args, &paths)?,
+    };
+    info!("Sketching {} files took {:?}", paths.len(), start.elapsed());
+
+    let mut w = BufWriter::new(create(&args.output)?);
+    for sketch in &sketches {
+        sketch.write(&mut w)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Add `path` if it is a file, or all FASTA/FASTQ files below it if it is a directory.
+fn collect_paths(path: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    if !path.is_dir() {
+        paths.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries = path
+        .read_dir()
+        .map_err(|e| format!("{}: {e}", path.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() || is_fastx(&entry) {
+            collect_paths(&entry, paths)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the file name has a FASTA or FASTQ extension, optionally followed by a compression extension.
+fn is_fastx(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = ["gz", "bz2", "xz", "zst"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext)?.strip_suffix('.'))
+        .unwrap_or(&name);
+    let ext = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+    ["fa", "fasta", "fna", "ffn", "fas", "fq", "fastq"].contains(&ext)
+}
+
+fn sketch_files<H: KmerHasher>(
+    sketcher: &Sketcher<H>,
+    args: &SketchArgs,
+    paths: &[PathBuf],
+) -> Result<Vec<AnySketch>> {
+    let sketches = sketcher
+        .sketch_many(paths, |sketcher, path| sketch_file(sketcher, args, path))
+        .into_iter()
+        .collect::<std::io::Result<Vec<_>>>()?;
+    Ok(sketches.into_iter().flatten().collect())
+}
+
+/// Sketch the file at `path`, or each of its records when `args.individual` is set.
+fn sketch_file<H: KmerHasher>(
+    sketcher: &Sketcher<H>,
+    args: &SketchArgs,
+    path: &Path,
+) -> std::io::Result<Vec<AnySketch>> {
+    with_fastx_records(path, |ids, records| {
+        if args.individual {
+            std::iter::zip(ids, records)
+                .map(|(id, record)| {
+                    let record = std::slice::from_ref(record);
+                    let metadata = Metadata::from_records(path, record);
+                    sketch_seqs(sketcher, args, record, id.clone(), metadata)
+                })
+                .collect()
+        } else {
+            let name = path.display().to_string();
+            let metadata = Metadata::from_records(path, records);
+            vec![sketch_seqs(sketcher, args, records, name, metadata)]
+        }
+    })
+}
+
+fn sketch_seqs<H: KmerHasher>(
+    sketcher: &Sketcher<H>,
+    args: &SketchArgs,
+    seqs: &[AsciiSeq],
+    name: String,
+    metadata: Metadata,
+) -> AnySketch {
+    match args.kind {
+        Kind::Bottom => {
+            let mut sketch = sketcher.bottom_sketch_seqs(seqs);
+            sketch.name = name;
+            sketch.metadata = metadata;
+            sketch.into()
+        }
+        Kind::Bucket => {
+            let mut sketch = sketcher.sketch_seqs(seqs);
+            sketch.name = name;
+            sketch.metadata = metadata;
+            sketch.into()
+        }
+        Kind::Scaled => {
+            let mut sketch = sketcher.scaled_sketch_seqs(seqs, args.scale);
+            sketch.name = name;
+            sketch.metadata = metadata;
+            sketch.into()
+        }
+    }
+}
+
+fn dist(args: &DistArgs) -> Result<()> {
+    let references = read_sketches(&args.references)?;
+    let queries = read_sketches(&args.query)?;
+    check_compatible(references.iter().chain(&queries))?;
+
+    let all_pairs = args.query.is_empty();
+    let queries = if all_pairs { &references } else { &queries };
+    let lines = queries
+        .par_iter()
+        .enumerate()
+        .map(|(i, query)| {
+            // For all pairs, only compare each query against the sketches after it.
+            let references = if all_pairs {
+                &references[i + 1..]
+            } else {
+                &references[..]
+            };
+            let mut lines = String::new();
+            for reference in references {
+                let d = query.distance(reference);
+                if args.max_distance.is_some_and(|max| d.mash_distance > max) {
+                    continue;
+                }
+                lines += &match args.format {
+                    Format::Tsv => format!(
+                        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                        query.name(),
+                        reference.name(),
+                        d.jaccard,
+                        d.mash_distance,
+                        d.ani,
+                        d.p_value,
+                        d.shared,
+                        d.total
+                    ),
+                    Format::Mash => format!(
+                        "{}\t{}\t{}\t{}\t{}/{}\n",
+                        reference.name(),
+                        query.name(),
+                        d.mash_distance,
+                        d.p_value,
+                        d.shared,
+                        d.total
+                    ),
+                };
+            }
+            lines
+        })
+        .collect::<Vec<_>>();
+
+    let mut w = BufWriter::new(std::io::stdout().lock());
+    if matches!(args.format, Format::Tsv) {
+        writeln!(
+            w,
+            "query\treference\tjaccard\tdistance\tani\tp_value\tshared\ttotal"
+        )?;
+    }
+    for lines in lines {
+        w.write_all(lines.as_bytes())?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+fn triangle(args: &TriangleArgs) -> Result<()> {
+    let sketches = read_sketches(&args.inputs)?;
+    check_compatible(&sketches)?;
+    let n = sketches.len();
+
+    // Bottom and bucket sketches are compared using the tiled similarity matrix.
+    let rows = match sketches.first().map(AnySketch::kind) {
+        Some("bottom") => {
+            let (names, sketches): (Vec<_>, Vec<_>) = sketches
+                .into_iter()
+                .map(|sketch| match sketch {
+                    AnySketch::Bottom(x) => (x.name.clone(), x),
+                    _ => unreachable!("sketches have the same type"),
+                })
+                .unzip();
+            let k = sketches[0].k();
+            matrix_rows(&names, k, &BottomSketch::similarity_matrix(&sketches))
+        }
+        Some("bucket") => {
+            let (names, sketches): (Vec<_>, Vec<_>) = sketches
+                .into_iter()
+                .map(|sketch| match sketch {
+                    AnySketch::Bucket(x) => (x.name.clone(), x),
+                    _ => unreachable!("sketches have the same type"),
+                })
+                .unzip();
+            let k = sketches[0].k();
+            matrix_rows(&names, k, &BucketSketch::similarity_matrix(&sketches))
+        }
+        _ => sketches
+            .par_iter()
+            .enumerate()
+            .map(|(i, sketch)| {
+                let mut row = sketch.name().to_string();
+                for other in &sketches[..i] {
+                    row += &format!("\t{}", sketch.distance(other).mash_distance);
+                }
+                row
+            })
+            .collect(),
+    };
+
+    let mut w = BufWriter::new(std::io::stdout().lock());
+    writeln!(w, "{n}")?;
+    for row in rows {
+        writeln!(w, "{row}")?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Format the rows of a PHYLIP lower-triangular distance matrix.
+fn matrix_rows(names: &[String], k: usize, matrix: &SimilarityMatrix) -> Vec<String> {
+    names
+        .par_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let mut row = name.clone();
+            for &similarity in matrix.row(i) {
+                row += &format!("\t{}", mash_distance(similarity, k));
+            }
+            row
+        })
+        .collect()
+}
+
+fn print_info(args: &InfoArgs) -> Result<()> {
+    let mut w = BufWriter::new(std::io::stdout().lock());
+    writeln!(
+        w,
+        "name\tkind\tk\tcanonical\thasher\tseed\thash_bits\tb\ts\tscale\tmin_count\tseq_len\tcardinality\tpath\trecords\tgc"
+    )?;
+    for sketch in read_sketches(&args.inputs)? {
+        let (rc, hasher, hash_bits, b, s, scale) = match &sketch {
+            AnySketch::Bottom(x) => (
+                x.rc(),
+                x.hasher(),
+                x.hash_bits(),
+                x.b(),
+                x.s().to_string(),
+                "-".to_string(),
+            ),
+            AnySketch::Bucket(x) => (
+                x.rc(),
+                x.hasher(),
+                x.hash_bits(),
+                x.b(),
+                x.s().to_string(),
+                "-".to_string(),
+            ),
+            AnySketch::Scaled(x) => (
+                x.rc(),
+                x.hasher(),
+                x.hash_bits(),
+                x.hash_bits(),
+                "-".to_string(),
+                x.scale().to_string(),
+            ),
+        };
+        let metadata = sketch.metadata();
+        let records = metadata.records.map_or("-".to_string(), |x| x.to_string());
+        let gc = metadata
+            .gc_content(sketch.seq_len())
+            .map_or("-".to_string(), |x| format!("{x:.4}"));
+        let hasher_name = match hasher.kind {
+            kind if kind == NtHash.id().kind => "nthash".to_string(),
+            kind if kind == KmerMix.id().kind => "kmer-mix".to_string(),
+            kind if kind == Murmur3.id().kind => "murmur3".to_string(),
+            kind => format!("custom-{kind}"),
+        };
+        writeln!(
+            w,
+            "{}\t{}\t{}\t{rc}\t{hasher_name}\t{}\t{hash_bits}\t{b}\t{s}\t{scale}\t{}\t{}\t{}\t{}\t{records}\t{gc}",
+            sketch.name(),
+            sketch.kind(),
+            sketch.k(),
+            hasher.seed,
+            sketch.min_count(),
+            sketch.seq_len(),
+            sketch.cardinality(),
+            if metadata.path.is_empty() {
+                "-"
+            } else {
+                &metadata.path
+            },
+        )?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+fn merge(args: &MergeArgs) -> Result<()> {
+    let sketches = read_sketches(&args.inputs)?;
+    check_compatible(&sketches)?;
+    for sketch in &sketches {
+        let mergeable = match sketch {
+            AnySketch::Bottom(x) => x.b() == x.hash_bits(),
+            AnySketch::Bucket(x) => x.b() == x.hash_bits() && !x.densified(),
+            AnySketch::Scaled(_) => true,
+        };
+        if !mergeable {
+            return Err(format!(
+                "{}: only sketches storing full hashes that are not densified can be merged.",
+                sketch.name()
+            )
+            .into());
+        }
+        if sketch.min_count() != sketches[0].min_count() {
+            return Err(format!(
+                "{}: only sketches with equal min counts can be merged.",
+                sketch.name()
+            )
+            .into());
+        }
+    }
+
+    let mut merged = AnySketch::merge_all(&sketches).ok_or("No sketches to merge.")?;
+    let name = args
+        .name
+        .clone()
+        .unwrap_or_else(|| args.output.display().to_string());
+    match &mut merged {
+        AnySketch::Bottom(x) => x.name = name,
+        AnySketch::Bucket(x) => x.name = name,
+        AnySketch::Scaled(x) => x.name = name,
+    }
+
+    let mut w = BufWriter::new(create(&args.output)?);
+    merged.write(&mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Read all sketches from the given sketch files, in order.
+fn read_sketches(paths: &[PathBuf]) -> Result<Vec<AnySketch>> {
+    let sketches = paths
+        .par_iter()
+        .map(|path| {
+            simd_sketch::serialize::read_all(&mut BufReader::new(open(path)?))
+                .map_err(|e| format!("{}: {e}", path.display()).into())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(sketches.into_iter().flatten().collect())
+}
+
+/// Check that all sketches can be compared with each other.
+fn check_compatible<'a>(sketches: impl IntoIterator<Item = &'a AnySketch>) -> Result<()> {
+    let mut sketches = sketches.into_iter();
+    let Some(first) = sketches.next() else {
+        return Ok(());
+    };
+    for sketch in sketches {
+        if !first.is_compatible(sketch) {
+            return Err(format!(
+                "Sketches {} and {} have different types or parameters and can not be compared.",
+                first.name(),
+                sketch.name()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+fn open(path: &Path) -> Result<File> {
+    File::open(path).map_err(|e| format!("{}: {e}", path.display()).into())
+}
+
+fn create(path: &Path) -> Result<File> {
+    File::create(path).map_err(|e| format!("{}: {e}", path.display()).into())
+}
+
+fn init_trace() {
+    use tracing::level_filters::LevelFilter;
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(
+            tracing_subscriber::EnvFilter::builder()
+                .with_default_directive(LevelFilter::WARN.into())
+                .from_env_lossy(),
+        )
+        .init();
+}
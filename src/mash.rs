@@ -0,0 +1,621 @@
+//! Import and export of [Mash](https://github.com/marbl/Mash) `.msh` sketch files.
+//!
+//! `.msh` files are unpacked [Cap'n Proto](https://capnproto.org/encoding.html) messages
+//! following Mash's `MinHash.capnp` schema. Each file stores one bottom sketch per reference,
+//! all with the same parameters.
+//!
+//! Only sketches of DNA using MurmurHash3 with seed 42, as created by [`Sketcher::new_mash`](crate::Sketcher::new_mash),
+//! are supported. The format and distances follow Mash 2.3, but are only tested against
+//! fixtures generated by a separate reimplementation (`tests/data/mash/generate.py`), not by Mash itself.
+
+use std::io::{self, Read, Write};
+
+use crate::{BitSketch, BottomSketch, KmerHasher, Metadata, Murmur3, bottom_kmers};
+
+/// The hash seed used by Mash. It is stored XOR'ed with its default value 42.
+const SEED: u32 = 42;
+const WINDOW_SIZE: u32 = 10000;
+
+/// Field offsets in the `MinHash` struct: data bytes and pointer indices.
+mod min_hash {
+    pub const DATA_WORDS: usize = 3;
+    pub const POINTERS: usize = 4;
+    pub const KMER_SIZE: usize = 0;
+    pub const WINDOW_SIZE: usize = 4;
+    pub const MIN_HASHES_PER_WINDOW: usize = 8;
+    /// Bits of byte 12.
+    pub const FLAGS: usize = 12;
+    pub const CONCATENATED: u8 = 1;
+    pub const NONCANONICAL: u8 = 2;
+    pub const PRESERVE_CASE: u8 = 4;
+    pub const ERROR: usize = 16;
+    pub const HASH_SEED: usize = 20;
+    pub const REFERENCE_LIST_OLD: usize = 0;
+    pub const REFERENCE_LIST: usize = 2;
+    pub const ALPHABET: usize = 3;
+}
+
+/// Field offsets in the `Reference` struct: data bytes and pointer indices.
+mod reference {
+    pub const DATA_WORDS: usize = 2;
+    pub const POINTERS: usize = 7;
+    pub const LENGTH: usize = 0;
+    pub const LENGTH64: usize = 8;
+    pub const NAME: usize = 2;
+    pub const HASHES32: usize = 4;
+    pub const HASHES64: usize = 5;
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Write `sketches` as a Mash `.msh` file.
+///
+/// All sketches must use the (unseeded) [`Murmur3`] hasher, store full hashes,
+/// use 32-bit hashes exactly when `k <= 16`, and share `k`, `s`, and `rc`.
+pub fn write_msh(sketches: &[BottomSketch], w: &mut impl Write) -> io::Result<()> {
+    let Some(first) = sketches.first() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Cannot write an empty .msh file.",
+        ));
+    };
+    for sketch in sketches {
+        let mash_bits = if sketch.k <= 16 { 32 } else { 64 };
+        if sketch.hasher != Murmur3.id()
+            || sketch.b != sketch.hash_bits
+            || sketch.hash_bits != mash_bits
+            || (sketch.k, sketch.s, sketch.rc) != (first.k, first.s, first.rc)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Only sketches created by `Sketcher::new_mash` with equal parameters can be written to .msh files.",
+            ));
+        }
+    }
+
+    let mut m = Builder::default();
+    let root = m.alloc(1);
+    let min_hash = m.alloc_struct(
+        Ptr { seg: 0, pos: root },
+        min_hash::DATA_WORDS,
+        min_hash::POINTERS,
+    );
+    m.set(
+        min_hash.data + min_hash::KMER_SIZE,
+        &(first.k as u32).to_le_bytes(),
+    );
+    m.set(
+        min_hash.data + min_hash::WINDOW_SIZE,
+        &WINDOW_SIZE.to_le_bytes(),
+    );
+    m.set(
+        min_hash.data + min_hash::MIN_HASHES_PER_WINDOW,
+        &(first.s as u32).to_le_bytes(),
+    );
+    let mut flags = min_hash::CONCATENATED;
+    if !first.rc {
+        flags |= min_hash::NONCANONICAL;
+    }
+    m.set(min_hash.data + min_hash::FLAGS, &[flags]);
+    m.set(min_hash.data + min_hash::ERROR, &0f32.to_le_bytes());
+    m.set(
+        min_hash.data + min_hash::HASH_SEED,
+        // Stored XOR'ed with the default.
+        &0u32.to_le_bytes(),
+    );
+    m.set_text(min_hash.pointer(min_hash::ALPHABET), "ACGT");
+
+    let list = m.alloc_struct(min_hash.pointer(min_hash::REFERENCE_LIST), 0, 1);
+    let references = m.alloc_struct_list(
+        list.pointer(0),
+        sketches.len(),
+        reference::DATA_WORDS,
+        reference::POINTERS,
+    );
+    for (r, sketch) in std::iter::zip(references, sketches) {
+        m.set(
+            r.data + reference::LENGTH,
+            &(sketch.seq_len.min(u32::MAX as u64) as u32).to_le_bytes(),
+        );
+        m.set(r.data + reference::LENGTH64, &sketch.seq_len.to_le_bytes());
+        m.set_text(r.pointer(reference::NAME), &sketch.name);
+        match &sketch.bottom {
+            BitSketch::B32(h) => m.set_list(r.pointer(reference::HASHES32), 4, &h[..sketch.len]),
+            BitSketch::B64(h) => m.set_list(r.pointer(reference::HASHES64), 5, &h[..sketch.len]),
+            _ => unreachable!(),
+        }
+    }
+
+    // A single segment.
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(&((m.bytes.len() / 8) as u32).to_le_bytes())?;
+    w.write_all(&m.bytes)
+}
+
+/// Read all sketches in a Mash `.msh` file.
+///
+/// The sketches use the [`Murmur3`] hasher and can only be compared to each other
+/// and to sketches created by [`Sketcher::new_mash`](crate::Sketcher::new_mash) with the same parameters.
+pub fn read_msh(r: &mut impl Read) -> io::Result<Vec<BottomSketch>> {
+    let mut bytes = vec![];
+    r.read_to_end(&mut bytes)?;
+    let m = Message::new(&bytes)?;
+
+    let min_hash = m.read_struct(Ptr { seg: 0, pos: 0 })?;
+    let k = m.read_u32(min_hash, min_hash::KMER_SIZE)? as usize;
+    let s = m.read_u32(min_hash, min_hash::MIN_HASHES_PER_WINDOW)? as usize;
+    let flags = m.read_bytes::<1>(min_hash, min_hash::FLAGS)?[0];
+    let seed = m.read_u32(min_hash, min_hash::HASH_SEED)? ^ SEED;
+    if seed != SEED {
+        return Err(invalid(format!(
+            "Unsupported Mash hash seed {seed}; only {SEED} is supported."
+        )));
+    }
+    if flags & min_hash::PRESERVE_CASE != 0 {
+        return Err(invalid("Case-preserving Mash sketches are not supported."));
+    }
+    let alphabet = m.read_text(min_hash.pointer(min_hash::ALPHABET))?;
+    if !alphabet.is_empty() && alphabet != b"ACGT" {
+        return Err(invalid("Only DNA Mash sketches are supported."));
+    }
+    if k == 0 || k > 32 || s == 0 {
+        return Err(invalid(format!("Invalid Mash parameters k={k}, s={s}.")));
+    }
+    let rc = flags & min_hash::NONCANONICAL == 0;
+    let hash_bits = if k <= 16 { 32 } else { 64 };
+
+    let mut list = m.read_struct(min_hash.pointer(min_hash::REFERENCE_LIST))?;
+    if list.pointers == 0 || m.word(list.pointer(0))? == 0 {
+        list = m.read_struct(min_hash.pointer(min_hash::REFERENCE_LIST_OLD))?;
+    }
+    let references = m.read_struct_list(list.pointer(0))?;
+
+    let mut sketches = Vec::with_capacity(references.len());
+    for r in references {
+        let name = String::from_utf8_lossy(&m.read_text(r.pointer(reference::NAME))?).into_owned();
+        let seq_len = match u64::from_le_bytes(m.read_bytes(r, reference::LENGTH64)?) {
+            0 => m.read_u32(r, reference::LENGTH)? as u64,
+            len => len,
+        };
+        let (len, kmers, bottom) = if hash_bits == 32 {
+            let hashes = m.read_list(r.pointer(reference::HASHES32), 4, u32::from_le_bytes)?;
+            let (len, kmers, hashes) = bottom_from_hashes(hashes, s);
+            (len, kmers, BitSketch::B32(hashes))
+        } else {
+            let hashes = m.read_list(r.pointer(reference::HASHES64), 5, u64::from_le_bytes)?;
+            let (len, kmers, hashes) = bottom_from_hashes(hashes, s);
+            (len, kmers, BitSketch::B64(hashes))
+        };
+        sketches.push(BottomSketch {
+            rc,
+            k,
+            s,
+            b: hash_bits,
+            hash_bits,
+            hasher: Murmur3.id(),
+            len,
+            kmers,
+            name,
+            metadata: Metadata::default(),
+            seq_len,
+            min_count: 1,
+            bottom,
+            abundances: None,
+        });
+    }
+    Ok(sketches)
+}
+
+/// Sort and deduplicate the hashes, keep at most `s`, and pad to length `s`.
+fn bottom_from_hashes<T: crate::HashValue>(mut hashes: Vec<T>, s: usize) -> (usize, u64, Vec<T>) {
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes.truncate(s);
+    let len = hashes.len();
+    let kmers = bottom_kmers(&hashes, s);
+    hashes.resize(s, T::MAX);
+    (len, kmers, hashes)
+}
+
+/// The location of a struct: the byte offset of its data section, and of its pointer section.
+#[derive(Clone, Copy)]
+struct Struct {
+    seg: usize,
+    data: usize,
+    data_bytes: usize,
+    ptrs: usize,
+    pointers: usize,
+}
+
+impl Struct {
+    /// The `i`th pointer, or a null pointer when out of range.
+    fn pointer(&self, i: usize) -> Ptr {
+        if i < self.pointers {
+            Ptr {
+                seg: self.seg,
+                pos: self.ptrs / 8 + i,
+            }
+        } else {
+            Ptr::NULL
+        }
+    }
+}
+
+/// The location of a pointer word.
+#[derive(Clone, Copy)]
+struct Ptr {
+    seg: usize,
+    pos: usize,
+}
+
+impl Ptr {
+    const NULL: Ptr = Ptr {
+        seg: usize::MAX,
+        pos: 0,
+    };
+}
+
+/// A Cap'n Proto message being read.
+struct Message<'a> {
+    segments: Vec<&'a [u8]>,
+}
+
+impl<'a> Message<'a> {
+    fn new(bytes: &'a [u8]) -> io::Result<Self> {
+        let u32_at = |i: usize| -> io::Result<u32> {
+            bytes
+                .get(4 * i..4 * i + 4)
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+                .ok_or_else(|| invalid("Truncated .msh file."))
+        };
+        let count = u32_at(0)? as usize + 1;
+        if count > 512 {
+            return Err(invalid("Too many segments in .msh file."));
+        }
+        let mut offset = (4 * (count + 1)).next_multiple_of(8);
+        let mut segments = Vec::with_capacity(count);
+        for i in 0..count {
+            let len = u32_at(i + 1)? as usize * 8;
+            let segment = bytes
+                .get(offset..offset + len)
+                .ok_or_else(|| invalid("Truncated .msh file."))?;
+            segments.push(segment);
+            offset += len;
+        }
+        Ok(Message { segments })
+    }
+
+    fn bytes(&self, seg: usize, start: usize, len: usize) -> io::Result<&'a [u8]> {
+        self.segments
+            .get(seg)
+            .and_then(|s| s.get(start..start.checked_add(len)?))
+            .ok_or_else(|| invalid("Out-of-bounds pointer in .msh file."))
+    }
+
+    fn word(&self, p: Ptr) -> io::Result<u64> {
+        if p.seg == usize::MAX {
+            return Ok(0);
+        }
+        Ok(u64::from_le_bytes(
+            self.bytes(p.seg, 8 * p.pos, 8)?.try_into().unwrap(),
+        ))
+    }
+
+    /// Follow a (possibly far) pointer.
+    /// Returns the pointer word describing the target, and the segment and word offset of the target.
+    fn resolve(&self, p: Ptr) -> io::Result<(u64, usize, usize)> {
+        let w = self.word(p)?;
+        if w & 3 != 2 {
+            let target = p.pos as i64 + 1 + ((w as u32 as i32) >> 2) as i64;
+            return Ok((w, p.seg, target.max(0) as usize));
+        }
+        // Far pointer to a landing pad in another segment.
+        let pad = Ptr {
+            seg: (w >> 32) as usize,
+            pos: ((w as u32) >> 3) as usize,
+        };
+        if w & 4 == 0 {
+            let w = self.word(pad)?;
+            if w & 3 == 2 {
+                return Err(invalid("Invalid far pointer in .msh file."));
+            }
+            let target = pad.pos as i64 + 1 + ((w as u32 as i32) >> 2) as i64;
+            return Ok((w, pad.seg, target.max(0) as usize));
+        }
+        // Double-far: the pad is a far pointer to the content, followed by a tag.
+        let far = self.word(pad)?;
+        let tag = self.word(Ptr {
+            seg: pad.seg,
+            pos: pad.pos + 1,
+        })?;
+        if far & 7 != 2 {
+            return Err(invalid("Invalid double-far pointer in .msh file."));
+        }
+        Ok((tag, (far >> 32) as usize, ((far as u32) >> 3) as usize))
+    }
+
+    fn read_struct(&self, p: Ptr) -> io::Result<Struct> {
+        let (w, seg, pos) = self.resolve(p)?;
+        if w == 0 {
+            return Ok(Struct {
+                seg: usize::MAX,
+                data: 0,
+                data_bytes: 0,
+                ptrs: 0,
+                pointers: 0,
+            });
+        }
+        if w & 3 != 0 {
+            return Err(invalid("Expected a struct pointer in .msh file."));
+        }
+        let data_words = (w >> 32) as u16 as usize;
+        let pointers = (w >> 48) as usize;
+        self.bytes(seg, 8 * pos, 8 * (data_words + pointers))?;
+        Ok(Struct {
+            seg,
+            data: 8 * pos,
+            data_bytes: 8 * data_words,
+            ptrs: 8 * (pos + data_words),
+            pointers,
+        })
+    }
+
+    /// Read `N` bytes of the data section, which are 0 when outside the struct.
+    fn read_bytes<const N: usize>(&self, s: Struct, offset: usize) -> io::Result<[u8; N]> {
+        if offset + N > s.data_bytes {
+            return Ok([0; N]);
+        }
+        Ok(self.bytes(s.seg, s.data + offset, N)?.try_into().unwrap())
+    }
+
+    fn read_u32(&self, s: Struct, offset: usize) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.read_bytes(s, offset)?))
+    }
+
+    /// Resolve a list pointer, returning its element size code, length, and location.
+    fn list(&self, p: Ptr) -> io::Result<Option<(u64, usize, usize, usize)>> {
+        let (w, seg, pos) = self.resolve(p)?;
+        if w == 0 {
+            return Ok(None);
+        }
+        if w & 3 != 1 {
+            return Err(invalid("Expected a list pointer in .msh file."));
+        }
+        Ok(Some(((w >> 32) & 7, (w >> 35) as usize, seg, pos)))
+    }
+
+    /// Read a list of primitive values with the given element size code.
+    fn read_list<T, const N: usize>(
+        &self,
+        p: Ptr,
+        size: u64,
+        from_bytes: impl Fn([u8; N]) -> T,
+    ) -> io::Result<Vec<T>> {
+        let Some((list_size, len, seg, pos)) = self.list(p)? else {
+            return Ok(vec![]);
+        };
+        if list_size != size {
+            return Err(invalid("Unexpected list element size in .msh file."));
+        }
+        Ok(self
+            .bytes(seg, 8 * pos, len * N)?
+            .chunks_exact(N)
+            .map(|x| from_bytes(x.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Read a text field, without the trailing NUL byte.
+    fn read_text(&self, p: Ptr) -> io::Result<Vec<u8>> {
+        let mut text = self.read_list(p, 2, |[b]: [u8; 1]| b)?;
+        if text.pop().is_some_and(|b| b != 0) {
+            return Err(invalid("Text is not NUL-terminated in .msh file."));
+        }
+        Ok(text)
+    }
+
+    fn read_struct_list(&self, p: Ptr) -> io::Result<Vec<Struct>> {
+        let Some((size, words, seg, pos)) = self.list(p)? else {
+            return Ok(vec![]);
+        };
+        if size != 7 {
+            return Err(invalid("Expected a list of structs in .msh file."));
+        }
+        let tag = self.word(Ptr { seg, pos })?;
+        let len = ((tag as u32) >> 2) as usize;
+        let data_words = (tag >> 32) as u16 as usize;
+        let pointers = (tag >> 48) as usize;
+        let stride = data_words + pointers;
+        if len.checked_mul(stride) != Some(words) {
+            return Err(invalid("Invalid struct list in .msh file."));
+        }
+        self.bytes(seg, 8 * (pos + 1), 8 * words)?;
+        Ok((0..len)
+            .map(|i| {
+                let start = pos + 1 + i * stride;
+                Struct {
+                    seg,
+                    data: 8 * start,
+                    data_bytes: 8 * data_words,
+                    ptrs: 8 * (start + data_words),
+                    pointers,
+                }
+            })
+            .collect())
+    }
+}
+
+/// A single-segment Cap'n Proto message being written.
+#[derive(Default)]
+struct Builder {
+    bytes: Vec<u8>,
+}
+
+impl Builder {
+    /// Allocate `words` zeroed words, returning the index of the first one.
+    fn alloc(&mut self, words: usize) -> usize {
+        let pos = self.bytes.len() / 8;
+        self.bytes.resize(self.bytes.len() + 8 * words, 0);
+        pos
+    }
+
+    fn set(&mut self, offset: usize, bytes: &[u8]) {
+        self.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Point `p` to `target`, with the low 32 bits of the pointer `kind` and the high 32 bits `hi`.
+    fn set_pointer(&mut self, p: Ptr, target: usize, kind: u64, hi: u64) {
+        let offset = (target as i64 - p.pos as i64 - 1) as i32 as u32 as u64;
+        self.set(
+            8 * p.pos,
+            &((offset << 2) | kind | (hi << 32)).to_le_bytes(),
+        );
+    }
+
+    fn alloc_struct(&mut self, p: Ptr, data_words: usize, pointers: usize) -> Struct {
+        let pos = self.alloc(data_words + pointers);
+        self.set_pointer(p, pos, 0, (data_words | pointers << 16) as u64);
+        Struct {
+            seg: 0,
+            data: 8 * pos,
+            data_bytes: 8 * data_words,
+            ptrs: 8 * (pos + data_words),
+            pointers,
+        }
+    }
+
+    fn alloc_struct_list(
+        &mut self,
+        p: Ptr,
+        len: usize,
+        data_words: usize,
+        pointers: usize,
+    ) -> Vec<Struct> {
+        let stride = data_words + pointers;
+        let pos = self.alloc(1 + len * stride);
+        self.set_pointer(p, pos, 1, 7 | ((len * stride) as u64) << 3);
+        let tag = (len as u64) << 2 | ((data_words | pointers << 16) as u64) << 32;
+        self.set(8 * pos, &tag.to_le_bytes());
+        (0..len)
+            .map(|i| {
+                let start = pos + 1 + i * stride;
+                Struct {
+                    seg: 0,
+                    data: 8 * start,
+                    data_bytes: 8 * data_words,
+                    ptrs: 8 * (start + data_words),
+                    pointers,
+                }
+            })
+            .collect()
+    }
+
+    /// Write a list of primitive values with the given element size code.
+    fn set_list<T: crate::HashValue>(&mut self, p: Ptr, size: u64, values: &[T]) {
+        let bytes = values
+            .iter()
+            .flat_map(|x| x.to_u64().to_le_bytes()[..T::BITS / 8].to_vec())
+            .collect::<Vec<_>>();
+        self.set_bytes(p, size, values.len(), &bytes);
+    }
+
+    fn set_text(&mut self, p: Ptr, text: &str) {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.push(0);
+        self.set_bytes(p, 2, bytes.len(), &bytes);
+    }
+
+    fn set_bytes(&mut self, p: Ptr, size: u64, len: usize, bytes: &[u8]) {
+        let pos = self.alloc(bytes.len().div_ceil(8));
+        self.set(8 * pos, bytes);
+        self.set_pointer(p, pos, 1, size | (len as u64) << 3);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn roundtrip() {
+    use packed_seq::SeqVec;
+
+    let seq = packed_seq::AsciiSeqVec::random(20000);
+    for k in [15, 21] {
+        let sketcher = crate::Sketcher::new_mash(k, 1000);
+        let mut a = sketcher.bottom_sketch(seq.as_slice());
+        a.name = "a".to_string();
+        // `b` has fewer than `s` distinct k-mers.
+        let mut b = sketcher.bottom_sketch(seq.slice(0..800));
+        b.name = "b ✓".to_string();
+        assert!(b.len < 800);
+
+        let mut bytes = vec![];
+        write_msh(&[a.clone(), b.clone()], &mut bytes).unwrap();
+        let read = read_msh(&mut &bytes[..]).unwrap();
+        assert_eq!(read, [a.clone(), b.clone()]);
+        assert!(read_msh(&mut &bytes[..bytes.len() - 1]).is_err());
+
+        // Sketches with different parameters or hash functions can not be exported.
+        let c = crate::Sketcher::new_mash(k, 2000).bottom_sketch(seq.as_slice());
+        assert!(write_msh(&[a.clone(), c], &mut vec![]).is_err());
+        let nthash = crate::Sketcher::new_rc(k, 1000, 32).bottom_sketch(seq.as_slice());
+        assert!(write_msh(&[nthash], &mut vec![]).is_err());
+    }
+}
+
+/// Read a file in `tests/data/mash`.
+#[cfg(test)]
+fn fixture(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/data/mash/{name}", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{path}: {e}"))
+}
+
+#[cfg(test)]
+#[test]
+fn mash_fixtures() {
+    // `k{k}.msh` and `k{k}.dist` are self-generated by `tests/data/mash/generate.py`, a separate
+    // reimplementation of `mash sketch -k {k} -s 100 a.fa b.fa` and `mash dist`, not by Mash itself.
+    for k in [21, 15] {
+        let sketches = read_msh(&mut &fixture(&format!("k{k}.msh"))[..]).unwrap();
+        assert_eq!(sketches.len(), 2);
+
+        // The hashes are those of `Sketcher::new_mash` on the same sequences.
+        let sketcher = crate::Sketcher::new_mash(k, 100);
+        for (sketch, file) in std::iter::zip(&sketches, ["a.fa", "b.fa"]) {
+            let seq = fixture(file)
+                .split(|&c| c == b'\n')
+                .filter(|line| !line.starts_with(b">"))
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>();
+            let mut expected = sketcher.bottom_sketch(packed_seq::AsciiSeq(&seq));
+            expected.name = file.to_string();
+            assert_eq!(*sketch, expected, "k={k} {file}");
+            assert_eq!((sketch.k, sketch.s, sketch.rc), (k, 100, true));
+            assert_eq!(sketch.seq_len, seq.len() as u64);
+            assert_eq!(sketch.b, if k <= 16 { 32 } else { 64 });
+        }
+
+        // Lines `reference query distance p-value shared/total`.
+        let dist = String::from_utf8(fixture(&format!("k{k}.dist"))).unwrap();
+        let mut lines = dist.lines();
+        for a in &sketches {
+            for b in &sketches {
+                let line = lines.next().unwrap();
+                let fields = line.split('\t').collect::<Vec<_>>();
+                assert_eq!(fields[..2], [&a.name, &b.name]);
+                let d = a.distance(b);
+                assert_eq!(format!("{}/{}", d.shared, d.total), fields[4], "{line}");
+                let distance = fields[2].parse::<f32>().unwrap();
+                assert!(
+                    (d.mash_distance - distance).abs() <= 1e-5 * distance,
+                    "{line}: {d:?}"
+                );
+                let p_value = fields[3].parse::<f64>().unwrap();
+                assert!(
+                    (d.p_value - p_value).abs() <= 1e-5 * p_value || d.p_value < 1e-300,
+                    "{line}: {d:?}"
+                );
+            }
+        }
+    }
+}
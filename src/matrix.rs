@@ -0,0 +1,320 @@
+//! Similarities between all pairs of sketches, computed in parallel over cache-sized tiles.
+
+use rayon::prelude::*;
+
+use crate::{BitSketch, BottomSketch, BucketSketch};
+
+/// Bytes of sketch data per tile of rows, and again per tile of columns.
+const TILE_BYTES: usize = 256 << 10;
+
+/// The similarities between all pairs of `n` sketches, stored as a lower-triangular matrix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimilarityMatrix {
+    n: usize,
+    /// Row `i` holds the similarities of sketch `i` to sketches `0..i`.
+    values: Vec<f32>,
+}
+
+/// The start of row `i` in the lower triangle.
+fn row_start(i: usize) -> usize {
+    i * i.saturating_sub(1) / 2
+}
+
+impl SimilarityMatrix {
+    /// The number of sketches.
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    /// Whether the matrix contains no sketches.
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// The similarity between sketches `i` and `j`. The diagonal is 1.
+    pub fn get(&self, i: usize, j: usize) -> f32 {
+        assert!(i < self.n && j < self.n);
+        match i.cmp(&j) {
+            std::cmp::Ordering::Equal => 1.0,
+            std::cmp::Ordering::Greater => self.values[row_start(i) + j],
+            std::cmp::Ordering::Less => self.values[row_start(j) + i],
+        }
+    }
+
+    /// The similarities of sketch `i` to sketches `0..i`.
+    pub fn row(&self, i: usize) -> &[f32] {
+        &self.values[row_start(i)..row_start(i + 1)]
+    }
+
+    /// The lower triangle without the diagonal, row by row: `(1,0), (2,0), (2,1), (3,0), ...`.
+    pub fn lower_triangle(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// The full symmetric `n x n` matrix in row-major order, with 1 on the diagonal.
+    pub fn to_full(&self) -> Vec<f32> {
+        let n = self.n;
+        let mut full = vec![1.0; n * n];
+        for i in 0..n {
+            for (j, &x) in self.row(i).iter().enumerate() {
+                full[i * n + j] = x;
+                full[j * n + i] = x;
+            }
+        }
+        full
+    }
+
+    /// Compute `f(i, j)` for all `j < i < n`.
+    ///
+    /// Rows are split into tiles of `tile` rows that are processed in parallel.
+    /// Each tile of rows iterates over tiles of `tile` columns,
+    /// so that both tiles of sketches stay in cache while they are compared.
+    fn new(n: usize, sketch_bytes: usize, f: impl Fn(usize, usize) -> f32 + Sync) -> Self {
+        // Use tiles that fit in cache, but make sure there is enough work for all threads.
+        let tile = (TILE_BYTES / sketch_bytes.max(1))
+            .min(n.div_ceil(4 * rayon::current_num_threads()))
+            .max(1);
+
+        let mut values = vec![0.0; row_start(n)];
+        let mut blocks = vec![];
+        let mut rest = &mut values[..];
+        for start in (0..n).step_by(tile) {
+            let end = (start + tile).min(n);
+            let (block, tail) = rest.split_at_mut(row_start(end) - row_start(start));
+            blocks.push((start, end, block));
+            rest = tail;
+        }
+
+        blocks.into_par_iter().for_each(|(start, end, block)| {
+            for col in (0..end).step_by(tile) {
+                for i in start.max(col + 1)..end {
+                    let row = &mut block[row_start(i) - row_start(start)..][..i];
+                    let end = (col + tile).min(i);
+                    for (j, x) in row[col..end].iter_mut().enumerate() {
+                        *x = f(i, col + j);
+                    }
+                }
+            }
+        });
+        SimilarityMatrix { n, values }
+    }
+}
+
+impl BottomSketch {
+    /// Compute the similarities between all pairs of sketches, as returned by [`BottomSketch::similarity`].
+    ///
+    /// Pairs are compared in parallel, in tiles of sketches that fit in cache.
+    pub fn similarity_matrix(sketches: &[Self]) -> SimilarityMatrix {
+        let sketch_bytes = sketches.first().map_or(0, |s| s.s * s.b / 8);
+        SimilarityMatrix::new(sketches.len(), sketch_bytes, |i, j| {
+            sketches[i].similarity(&sketches[j])
+        })
+    }
+}
+
+impl BucketSketch {
+    /// Compute the similarities between all pairs of sketches, as returned by [`BucketSketch::similarity`].
+    ///
+    /// The buckets of all sketches are first copied into a single contiguous array.
+    /// Pairs are then compared in parallel, in tiles of sketches that fit in cache,
+    /// using the same vectorized kernel as `similarity`.
+    pub fn similarity_matrix(sketches: &[Self]) -> SimilarityMatrix {
+        if let Some(first) = sketches.first() {
+            for other in sketches {
+                first.check_compatible(other);
+            }
+        }
+        let packed = PackedBuckets::new(sketches);
+        SimilarityMatrix::new(sketches.len(), packed.sketch_bytes(), |i, j| {
+            packed.similarity(i, &packed, j)
+        })
+    }
+}
+
+/// The buckets and empty masks of compatible bucket sketches, stored contiguously.
+pub(crate) struct PackedBuckets {
+    s: usize,
+    b: usize,
+    buckets: BitSketch,
+    mask_words: usize,
+    /// The empty masks of all sketches, or nothing when no sketch has empty buckets.
+    empty: Vec<u64>,
+}
+
+impl PackedBuckets {
+    pub(crate) fn new(sketches: &[BucketSketch]) -> Self {
+        let Some(first) = sketches.first() else {
+            return PackedBuckets {
+                s: 0,
+                b: 0,
+                buckets: BitSketch::B64(vec![]),
+                mask_words: 0,
+                empty: vec![],
+            };
+        };
+        let s = first.s();
+        for other in sketches {
+            assert_eq!(s, other.s(), "Cannot compare sketches of different sizes.");
+        }
+
+        let buckets = match &first.buckets {
+            BitSketch::B64(_) => BitSketch::B64(concat(sketches, |b| match b {
+                BitSketch::B64(v) => v,
+                _ => panic!("Bit width mismatch"),
+            })),
+            BitSketch::B32(_) => BitSketch::B32(concat(sketches, |b| match b {
+                BitSketch::B32(v) => v,
+                _ => panic!("Bit width mismatch"),
+            })),
+            BitSketch::B16(_) => BitSketch::B16(concat(sketches, |b| match b {
+                BitSketch::B16(v) => v,
+                _ => panic!("Bit width mismatch"),
+            })),
+            BitSketch::B8(_) => BitSketch::B8(concat(sketches, |b| match b {
+                BitSketch::B8(v) => v,
+                _ => panic!("Bit width mismatch"),
+            })),
+            BitSketch::B1(_) => BitSketch::B1(concat(sketches, |b| match b {
+                BitSketch::B1(v) => v,
+                _ => panic!("Bit width mismatch"),
+            })),
+        };
+        let mask_words = s.div_ceil(64);
+        let empty = if sketches.iter().any(|x| !x.empty.is_empty()) {
+            let mut empty = vec![0u64; sketches.len() * mask_words];
+            for (mask, x) in std::iter::zip(empty.chunks_exact_mut(mask_words), sketches) {
+                mask[..x.empty.len()].copy_from_slice(&x.empty);
+            }
+            empty
+        } else {
+            vec![]
+        };
+        PackedBuckets {
+            s,
+            b: first.b,
+            buckets,
+            mask_words,
+            empty,
+        }
+    }
+
+    /// The number of bytes of buckets per sketch.
+    pub(crate) fn sketch_bytes(&self) -> usize {
+        self.s * self.b / 8
+    }
+
+    /// The number of buckets that are empty in both sketch `i` and sketch `j` of `other`.
+    fn both_empty(&self, i: usize, other: &Self, j: usize) -> usize {
+        if self.empty.is_empty() || other.empty.is_empty() {
+            return 0;
+        }
+        std::iter::zip(
+            row(&self.empty, self.mask_words, i),
+            row(&other.empty, other.mask_words, j),
+        )
+        .map(|(a, b)| (a & b).count_ones())
+        .sum::<u32>() as usize
+    }
+
+    /// The similarity between sketch `i` and sketch `j` of `other`.
+    pub(crate) fn similarity(&self, i: usize, other: &Self, j: usize) -> f32 {
+        let s = self.s;
+        let matches = match (&self.buckets, &other.buckets) {
+            (BitSketch::B64(a), BitSketch::B64(b)) => {
+                BucketSketch::inner_matches(row(a, s, i), row(b, s, j))
+            }
+            (BitSketch::B32(a), BitSketch::B32(b)) => {
+                BucketSketch::inner_matches(row(a, s, i), row(b, s, j))
+            }
+            (BitSketch::B16(a), BitSketch::B16(b)) => {
+                BucketSketch::inner_matches(row(a, s, i), row(b, s, j))
+            }
+            (BitSketch::B8(a), BitSketch::B8(b)) => {
+                BucketSketch::inner_matches(row(a, s, i), row(b, s, j))
+            }
+            (BitSketch::B1(a), BitSketch::B1(b)) => {
+                BucketSketch::b1_matches(row(a, s / 64, i), row(b, s / 64, j))
+            }
+            _ => panic!("Bit width mismatch"),
+        };
+        BucketSketch::similarity_from_matches(self.b, matches, s - self.both_empty(i, other, j))
+    }
+
+    /// The similarity between sketch `i` and sketch `j` of `other`, if it is at least `bound`.
+    ///
+    /// For `b=1`, the words are compared in chunks, and the comparison stops as soon as
+    /// the number of matches so far plus the number of remaining buckets can not reach `bound`.
+    pub(crate) fn similarity_at_least(
+        &self,
+        i: usize,
+        other: &Self,
+        j: usize,
+        bound: f32,
+    ) -> Option<f32> {
+        const CHUNK: usize = 4;
+        let (BitSketch::B1(a), BitSketch::B1(b)) = (&self.buckets, &other.buckets) else {
+            return Some(self.similarity(i, other, j)).filter(|&x| x >= bound);
+        };
+        let total = self.s - self.both_empty(i, other, j);
+        let (a, b) = (row(a, self.s / 64, i), row(b, self.s / 64, j));
+        let mut matches = 0;
+        let mut remaining = self.s;
+        for (a, b) in std::iter::zip(a.chunks(CHUNK), b.chunks(CHUNK)) {
+            matches += BucketSketch::b1_matches(a, b);
+            remaining -= 64 * a.len();
+            // The similarity increases with the number of matches.
+            if BucketSketch::similarity_from_matches(1, matches + remaining, total) < bound {
+                return None;
+            }
+        }
+        Some(BucketSketch::similarity_from_matches(1, matches, total)).filter(|&x| x >= bound)
+    }
+}
+
+fn concat<T: Copy>(sketches: &[BucketSketch], values: impl Fn(&BitSketch) -> &Vec<T>) -> Vec<T> {
+    sketches
+        .iter()
+        .flat_map(|x| values(&x.buckets))
+        .copied()
+        .collect()
+}
+
+/// Row `i` of a contiguous array with rows of length `len`.
+fn row<T>(values: &[T], len: usize, i: usize) -> &[T] {
+    &values[i * len..(i + 1) * len]
+}
+
+#[cfg(test)]
+#[test]
+fn similarity_matrix() {
+    use packed_seq::{PackedSeqVec, SeqVec};
+
+    let base = PackedSeqVec::random(20000);
+    // Overlapping substrings have a range of similarities; short ones leave buckets empty.
+    let seqs = (0..40)
+        .map(|i| base.slice(100 * i..(100 * i + 500 * (i % 7 + 1)).min(20000)))
+        .collect::<Vec<_>>();
+    for b in [1, 8, 16, 32] {
+        let mut sketcher = crate::Sketcher::new_rc(21, 256, b);
+        sketcher.filter_empty = true;
+        let buckets = seqs.iter().map(|&x| sketcher.sketch(x)).collect::<Vec<_>>();
+        let bottoms = seqs
+            .iter()
+            .map(|&x| sketcher.bottom_sketch(x))
+            .collect::<Vec<_>>();
+        let bucket_matrix = BucketSketch::similarity_matrix(&buckets);
+        let bottom_matrix = BottomSketch::similarity_matrix(&bottoms);
+        assert_eq!(bucket_matrix.len(), seqs.len());
+        for i in 0..seqs.len() {
+            for j in 0..i {
+                assert_eq!(bucket_matrix.get(i, j), buckets[i].similarity(&buckets[j]));
+                assert_eq!(bucket_matrix.get(j, i), bucket_matrix.get(i, j));
+                assert_eq!(bottom_matrix.get(i, j), bottoms[i].similarity(&bottoms[j]));
+            }
+            assert_eq!(bucket_matrix.get(i, i), 1.0);
+        }
+        let full = bucket_matrix.to_full();
+        assert_eq!(full[3 * seqs.len() + 5], bucket_matrix.get(3, 5));
+    }
+    assert!(BucketSketch::similarity_matrix(&[]).is_empty());
+}
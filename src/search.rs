@@ -0,0 +1,237 @@
+//! Searching a collection of reference sketches for the references most similar to each query.
+
+use rayon::prelude::*;
+
+use crate::{BottomSketch, BucketSketch, matrix::PackedBuckets};
+
+/// Bytes of reference sketches per tile, compared against a block of queries at a time.
+const TILE_BYTES: usize = 256 << 10;
+
+/// A reference that is similar to a query.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    /// The index of the query sketch.
+    pub query: usize,
+    /// The index of the reference sketch.
+    pub reference: usize,
+    /// The similarity between the query and the reference.
+    pub similarity: f32,
+}
+
+/// The best hits of a single query.
+struct TopHits {
+    /// The minimal similarity of new hits: the threshold, or the `k`th best similarity so far.
+    threshold: f32,
+    top_k: Option<usize>,
+    /// Hits as `(similarity, reference)`. When `top_k` is set, this is regularly truncated to the best `k`.
+    hits: Vec<(f32, usize)>,
+}
+
+/// Sort by decreasing similarity, and then by reference.
+fn cmp_hits(a: &(f32, usize), b: &(f32, usize)) -> std::cmp::Ordering {
+    b.0.total_cmp(&a.0).then(a.1.cmp(&b.1))
+}
+
+impl TopHits {
+    fn new(threshold: Option<f32>, top_k: Option<usize>) -> Self {
+        TopHits {
+            threshold: threshold.unwrap_or(f32::NEG_INFINITY),
+            top_k,
+            hits: vec![],
+        }
+    }
+
+    fn push(&mut self, reference: usize, similarity: f32) {
+        if similarity < self.threshold {
+            return;
+        }
+        self.hits.push((similarity, reference));
+        if let Some(k) = self.top_k
+            && self.hits.len() >= 2 * k.max(1)
+        {
+            // Keep the best `k`, and raise the bound to the worst of them.
+            self.hits.select_nth_unstable_by(k.max(1) - 1, cmp_hits);
+            self.hits.truncate(k);
+            if let Some(&(worst, _)) = self.hits.last() {
+                self.threshold = self.threshold.max(worst);
+            }
+        }
+    }
+
+    fn finish(mut self, query: usize) -> impl Iterator<Item = Hit> {
+        self.hits.sort_unstable_by(cmp_hits);
+        self.hits.truncate(self.top_k.unwrap_or(usize::MAX));
+        self.hits
+            .into_iter()
+            .map(move |(similarity, reference)| Hit {
+                query,
+                reference,
+                similarity,
+            })
+    }
+}
+
+/// Find the hits of all queries, processing blocks of queries in parallel,
+/// and comparing each block against tiles of references that fit in cache.
+///
+/// `similarity(q, r, bound)` returns the similarity when it is at least `bound`.
+fn search(
+    queries: usize,
+    references: usize,
+    sketch_bytes: usize,
+    threshold: Option<f32>,
+    top_k: Option<usize>,
+    similarity: impl Fn(usize, usize, f32) -> Option<f32> + Sync,
+) -> Vec<Hit> {
+    let tile = (TILE_BYTES / sketch_bytes.max(1)).max(1);
+    let block = tile
+        .min(queries.div_ceil(4 * rayon::current_num_threads()))
+        .max(1);
+    let blocks = (0..queries).step_by(block).collect::<Vec<_>>();
+    blocks
+        .into_par_iter()
+        .flat_map_iter(|start| {
+            let end = (start + block).min(queries);
+            let mut tops = (start..end)
+                .map(|_| TopHits::new(threshold, top_k))
+                .collect::<Vec<_>>();
+            for ref_start in (0..references).step_by(tile) {
+                for (q, top) in (start..end).zip(&mut tops) {
+                    for r in ref_start..(ref_start + tile).min(references) {
+                        if let Some(s) = similarity(q, r, top.threshold) {
+                            top.push(r, s);
+                        }
+                    }
+                }
+            }
+            (start..end)
+                .zip(tops)
+                .flat_map(|(q, top)| top.finish(q))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+impl BottomSketch {
+    /// For each query, find the references with similarity at least `threshold`.
+    /// When `top_k` is set, only the `k` most similar of those are returned.
+    ///
+    /// Hits are sorted by query, and then by decreasing similarity.
+    pub fn search(
+        queries: &[Self],
+        references: &[Self],
+        threshold: Option<f32>,
+        top_k: Option<usize>,
+    ) -> Vec<Hit> {
+        let sketch_bytes = references.first().map_or(0, |s| s.s * s.b / 8);
+        search(
+            queries.len(),
+            references.len(),
+            sketch_bytes,
+            threshold,
+            top_k,
+            |q, r, _| Some(queries[q].similarity(&references[r])),
+        )
+    }
+}
+
+impl BucketSketch {
+    /// For each query, find the references with similarity at least `threshold`.
+    /// When `top_k` is set, only the `k` most similar of those are returned.
+    ///
+    /// Hits are sorted by query, and then by decreasing similarity.
+    ///
+    /// Sketches are copied into a contiguous layout as for [`BucketSketch::similarity_matrix`].
+    /// For `b=1`, comparisons stop early when the similarity can not exceed
+    /// the threshold or the `k`th best similarity found so far.
+    pub fn search(
+        queries: &[Self],
+        references: &[Self],
+        threshold: Option<f32>,
+        top_k: Option<usize>,
+    ) -> Vec<Hit> {
+        if let Some(first) = queries.first().or(references.first()) {
+            for other in queries.iter().chain(references) {
+                first.check_compatible(other);
+            }
+            if let (Some(q), Some(r)) = (queries.first(), references.first()) {
+                assert_eq!(q.s(), r.s(), "Cannot compare sketches of different sizes.");
+            }
+        }
+        let packed_queries = PackedBuckets::new(queries);
+        let packed_references = PackedBuckets::new(references);
+        search(
+            queries.len(),
+            references.len(),
+            packed_references.sketch_bytes(),
+            threshold,
+            top_k,
+            |q, r, bound| packed_queries.similarity_at_least(q, &packed_references, r, bound),
+        )
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn search_hits() {
+    use packed_seq::{PackedSeqVec, SeqVec};
+
+    let base = PackedSeqVec::random(20000);
+    let seqs = (0..30)
+        .map(|i| base.slice(200 * i..(200 * i + 1000 * (i % 5 + 1)).min(20000)))
+        .collect::<Vec<_>>();
+    for b in [1, 8, 32] {
+        let mut sketcher = crate::Sketcher::new_rc(21, 512, b);
+        sketcher.filter_empty = true;
+        let buckets = seqs.iter().map(|&x| sketcher.sketch(x)).collect::<Vec<_>>();
+        let bottoms = seqs
+            .iter()
+            .map(|&x| sketcher.bottom_sketch(x))
+            .collect::<Vec<_>>();
+        let (queries, references) = buckets.split_at(5);
+
+        for (threshold, top_k) in [
+            (None, None),
+            (Some(0.3), None),
+            (None, Some(3)),
+            (Some(0.2), Some(2)),
+        ] {
+            let hits = BucketSketch::search(queries, references, threshold, top_k);
+            // Compare against filtering all pairs.
+            let mut expected = vec![];
+            for (q, query) in queries.iter().enumerate() {
+                let mut row = references
+                    .iter()
+                    .enumerate()
+                    .map(|(r, reference)| (query.similarity(reference), r))
+                    .filter(|&(s, _)| s >= threshold.unwrap_or(f32::NEG_INFINITY))
+                    .collect::<Vec<_>>();
+                row.sort_by(cmp_hits);
+                row.truncate(top_k.unwrap_or(usize::MAX));
+                expected.extend(row.into_iter().map(|(similarity, reference)| Hit {
+                    query: q,
+                    reference,
+                    similarity,
+                }));
+            }
+            assert_eq!(
+                hits, expected,
+                "b={b} threshold={threshold:?} top_k={top_k:?}"
+            );
+
+            let hits = BottomSketch::search(&bottoms[..5], &bottoms[5..], threshold, top_k);
+            assert!(hits.iter().all(|h| {
+                h.similarity == bottoms[h.query].similarity(&bottoms[5 + h.reference])
+            }));
+            if top_k.is_none() {
+                let count = (0..5)
+                    .flat_map(|q| (5..30).map(move |r| (q, r)))
+                    .filter(|&(q, r)| {
+                        bottoms[q].similarity(&bottoms[r]) >= threshold.unwrap_or(f32::NEG_INFINITY)
+                    })
+                    .count();
+                assert_eq!(hits.len(), count);
+            }
+        }
+    }
+}
@@ -0,0 +1,783 @@
+//! A versioned, endian-stable binary format for sketches.
+//!
+//! All integers are stored little-endian. Each sketch is stored as:
+//!
+//! | field       | type       | notes                                                   |
+//! |-------------|------------|---------------------------------------------------------|
+//! | magic       | `[u8; 4]`  | `SSKH`                                                  |
+//! | version     | `u16`      | [`FORMAT_VERSION`]                                      |
+//! | kind        | `u8`       | 0: bottom, 1: bucket, 2: scaled                         |
+//! | hasher      | `u8`       | [`HasherId::kind`]: 0: ntHash, 1: k-mer mix, 2: Murmur3 |
+//! | hash bits   | `u8`       | 32 or 64                                                |
+//! | b           | `u8`       | stored bits per value                                   |
+//! | flags       | `u8`       | bit 0: rc, bit 1: densified, bit 2: empty mask present, bit 3: abundances present |
+//! | reserved    | `u8`       | 0                                                       |
+//! | k           | `u32`      |                                                         |
+//! | seed        | `u64`      | [`HasherId::seed`]                                      |
+//! | s           | `u64`      | 0 for scaled sketches                                   |
+//! | scale       | `u64`      | 0 for bottom and bucket sketches                        |
+//! | len         | `u64`      | number of distinct hashes (bottom sketches only)        |
+//! | kmers       | `u64`      | estimated number of distinct k-mers                     |
+//! | seq len     | `u64`      | total sequence length                                   |
+//! | min count   | `u64`      | [`Sketcher::min_count`](crate::Sketcher::min_count) |
+//! | name        | `u32` + bytes | UTF-8                                                |
+//! | path        | `u32` + bytes | UTF-8 [`Metadata::path`](crate::Metadata::path) |
+//! | records     | `u64`      | [`Metadata::records`](crate::Metadata::records), `u64::MAX` when unknown |
+//! | gc          | `u64`      | [`Metadata::gc`](crate::Metadata::gc), `u64::MAX` when unknown |
+//! | values      | `u64` + words | number of words, followed by words of `b` bits, or 64 bits for `b=1` |
+//! | empty mask  | `u64` + `u64`s | only when flag bit 2 is set                          |
+//! | abundances  | `u64` + `u64`s | one per hash; only when flag bit 3 is set |
+//! | checksum    | `u32`      | CRC-32 (IEEE) of all preceding bytes of the sketch      |
+//!
+//! Multiple sketches can be written to the same stream one after the other.
+//! Streams containing different types of sketches can be read using [`AnySketch::read`] and [`read_all`].
+
+use std::io::{self, BufRead, Read, Write};
+
+use crate::{AnySketch, BitSketch, BottomSketch, BucketSketch, HasherId, Metadata, ScaledSketch};
+
+const MAGIC: [u8; 4] = *b"SSKH";
+/// The current version of the binary sketch format.
+pub const FORMAT_VERSION: u16 = 1;
+
+const KIND_BOTTOM: u8 = 0;
+const KIND_BUCKET: u8 = 1;
+const KIND_SCALED: u8 = 2;
+
+const FLAG_RC: u8 = 1;
+const FLAG_DENSIFIED: u8 = 2;
+const FLAG_EMPTY: u8 = 4;
+const FLAG_ABUNDANCES: u8 = 8;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// The fields shared by all sketch types.
+struct Header {
+    kind: u8,
+    hasher: HasherId,
+    hash_bits: usize,
+    b: usize,
+    flags: u8,
+    k: usize,
+    s: usize,
+    scale: u64,
+    len: usize,
+    kmers: u64,
+    seq_len: u64,
+    min_count: usize,
+    name: String,
+    metadata: Metadata,
+}
+
+impl Header {
+    /// Check that `k` is valid for the hasher.
+    fn check_k(&self) -> io::Result<()> {
+        // The k-mer mix hasher only supports `k <= 16`.
+        if self.k == 0 || (self.hasher.kind == 1 && self.k > 16) {
+            return Err(invalid(format!("Invalid k={}.", self.k)));
+        }
+        Ok(())
+    }
+}
+
+impl BottomSketch {
+    /// Write the sketch in the binary format described in [`serialize`](crate::serialize).
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut flags = if self.rc { FLAG_RC } else { 0 };
+        if self.abundances.is_some() {
+            flags |= FLAG_ABUNDANCES;
+        }
+        let header = Header {
+            kind: KIND_BOTTOM,
+            hasher: self.hasher,
+            hash_bits: self.hash_bits,
+            b: self.b,
+            flags,
+            k: self.k,
+            s: self.s,
+            scale: 0,
+            len: self.len,
+            kmers: self.kmers,
+            seq_len: self.seq_len,
+            min_count: self.min_count,
+            name: self.name.clone(),
+            metadata: self.metadata.clone(),
+        };
+        write_sketch(w, &header, &self.bottom, &[], self.abundances())
+    }
+
+    /// Read a sketch written by [`BottomSketch::write`].
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let (h, bottom, _, abundances) = read_sketch(r, Some(KIND_BOTTOM))?;
+        Self::from_parts(h, bottom, abundances)
+    }
+
+    fn from_parts(h: Header, bottom: BitSketch, abundances: Option<Vec<u64>>) -> io::Result<Self> {
+        h.check_k()?;
+        if h.b < 16 {
+            return Err(invalid(format!("Invalid bottom sketch with b={}.", h.b)));
+        }
+        if h.len > h.s {
+            return Err(invalid("Bottom sketch has more than s hashes."));
+        }
+        // Full hashes are padded to length `s`.
+        let expected = if h.b == h.hash_bits { h.s } else { h.len };
+        if num_words(&bottom) != expected {
+            return Err(invalid(format!(
+                "Expected {expected} values, but found {}.",
+                num_words(&bottom)
+            )));
+        }
+        if abundances.as_ref().is_some_and(|a| a.len() != h.len) {
+            return Err(invalid("Number of abundances and hashes differ."));
+        }
+        Ok(BottomSketch {
+            rc: h.flags & FLAG_RC != 0,
+            k: h.k,
+            s: h.s,
+            b: h.b,
+            hash_bits: h.hash_bits,
+            hasher: h.hasher,
+            len: h.len,
+            kmers: h.kmers,
+            name: h.name,
+            metadata: h.metadata,
+            seq_len: h.seq_len,
+            min_count: h.min_count,
+            bottom,
+            abundances,
+        })
+    }
+
+    /// Serialize the sketch into a byte vector.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Deserialize a sketch from bytes written by [`BottomSketch::to_bytes`].
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<Self> {
+        Self::read(&mut bytes)
+    }
+}
+
+impl BucketSketch {
+    /// Write the sketch in the binary format described in [`serialize`](crate::serialize).
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut flags = if self.rc { FLAG_RC } else { 0 };
+        if self.densified {
+            flags |= FLAG_DENSIFIED;
+        }
+        if !self.empty.is_empty() {
+            flags |= FLAG_EMPTY;
+        }
+        let header = Header {
+            kind: KIND_BUCKET,
+            hasher: self.hasher,
+            hash_bits: self.hash_bits,
+            b: self.b,
+            flags,
+            k: self.k,
+            s: self.s(),
+            scale: 0,
+            len: 0,
+            kmers: self.kmers,
+            seq_len: self.seq_len,
+            min_count: self.min_count,
+            name: self.name.clone(),
+            metadata: self.metadata.clone(),
+        };
+        write_sketch(w, &header, &self.buckets, &self.empty, None)
+    }
+
+    /// Read a sketch written by [`BucketSketch::write`].
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let (h, buckets, empty, _) = read_sketch(r, Some(KIND_BUCKET))?;
+        Self::from_parts(h, buckets, empty)
+    }
+
+    fn from_parts(h: Header, buckets: BitSketch, empty: Vec<u64>) -> io::Result<Self> {
+        h.check_k()?;
+        // `b=1` values are packed 64 per word.
+        let values = if h.b == 1 {
+            64 * num_words(&buckets)
+        } else {
+            num_words(&buckets)
+        };
+        if h.s == 0 || values != h.s {
+            return Err(invalid(format!(
+                "Expected {} buckets, but found {values}.",
+                h.s
+            )));
+        }
+        if h.flags & FLAG_EMPTY != 0 && empty.len() != h.s.div_ceil(64) {
+            return Err(invalid(format!(
+                "Expected {} words of empty mask, but found {}.",
+                h.s.div_ceil(64),
+                empty.len()
+            )));
+        }
+        Ok(BucketSketch {
+            rc: h.flags & FLAG_RC != 0,
+            k: h.k,
+            b: h.b,
+            hash_bits: h.hash_bits,
+            hasher: h.hasher,
+            densified: h.flags & FLAG_DENSIFIED != 0,
+            kmers: h.kmers,
+            name: h.name,
+            metadata: h.metadata,
+            seq_len: h.seq_len,
+            min_count: h.min_count,
+            buckets,
+            empty,
+        })
+    }
+
+    /// Serialize the sketch into a byte vector.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Deserialize a sketch from bytes written by [`BucketSketch::to_bytes`].
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<Self> {
+        Self::read(&mut bytes)
+    }
+}
+
+impl ScaledSketch {
+    /// Write the sketch in the binary format described in [`serialize`](crate::serialize).
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut flags = if self.rc { FLAG_RC } else { 0 };
+        if self.abundances.is_some() {
+            flags |= FLAG_ABUNDANCES;
+        }
+        let header = Header {
+            kind: KIND_SCALED,
+            hasher: self.hasher,
+            hash_bits: self.hash_bits,
+            b: self.hash_bits,
+            flags,
+            k: self.k,
+            s: 0,
+            scale: self.scale,
+            len: 0,
+            kmers: self.len() as u64 * self.scale,
+            seq_len: self.seq_len,
+            min_count: self.min_count,
+            name: self.name.clone(),
+            metadata: self.metadata.clone(),
+        };
+        write_sketch(w, &header, &self.hashes, &[], self.abundances())
+    }
+
+    /// Read a sketch written by [`ScaledSketch::write`].
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let (h, hashes, _, abundances) = read_sketch(r, Some(KIND_SCALED))?;
+        Self::from_parts(h, hashes, abundances)
+    }
+
+    fn from_parts(h: Header, hashes: BitSketch, abundances: Option<Vec<u64>>) -> io::Result<Self> {
+        h.check_k()?;
+        if h.scale == 0 || h.b != h.hash_bits {
+            return Err(invalid("Invalid scaled sketch."));
+        }
+        let len = match &hashes {
+            BitSketch::B32(h) => h.len(),
+            BitSketch::B64(h) => h.len(),
+            _ => unreachable!(),
+        };
+        if abundances.as_ref().is_some_and(|a| a.len() != len) {
+            return Err(invalid("Number of abundances and hashes differ."));
+        }
+        Ok(ScaledSketch {
+            rc: h.flags & FLAG_RC != 0,
+            k: h.k,
+            hash_bits: h.hash_bits,
+            hasher: h.hasher,
+            scale: h.scale,
+            name: h.name,
+            metadata: h.metadata,
+            seq_len: h.seq_len,
+            min_count: h.min_count,
+            hashes,
+            abundances,
+        })
+    }
+
+    /// Serialize the sketch into a byte vector.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Deserialize a sketch from bytes written by [`ScaledSketch::to_bytes`].
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<Self> {
+        Self::read(&mut bytes)
+    }
+}
+
+impl AnySketch {
+    /// Write the sketch in the binary format described in [`serialize`](crate::serialize).
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        match self {
+            AnySketch::Bottom(s) => s.write(w),
+            AnySketch::Bucket(s) => s.write(w),
+            AnySketch::Scaled(s) => s.write(w),
+        }
+    }
+
+    /// Read a sketch of any type.
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let (h, values, empty, abundances) = read_sketch(r, None)?;
+        Ok(match h.kind {
+            KIND_BOTTOM => AnySketch::Bottom(BottomSketch::from_parts(h, values, abundances)?),
+            KIND_BUCKET => AnySketch::Bucket(BucketSketch::from_parts(h, values, empty)?),
+            _ => AnySketch::Scaled(ScaledSketch::from_parts(h, values, abundances)?),
+        })
+    }
+
+    /// Serialize the sketch into a byte vector.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    /// Deserialize a sketch from bytes written by [`AnySketch::to_bytes`].
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<Self> {
+        Self::read(&mut bytes)
+    }
+}
+
+/// Read sketches of any type until the end of the stream.
+pub fn read_all(r: &mut impl BufRead) -> io::Result<Vec<AnySketch>> {
+    let mut sketches = vec![];
+    while !r.fill_buf()?.is_empty() {
+        sketches.push(AnySketch::read(r)?);
+    }
+    Ok(sketches)
+}
+
+fn write_sketch(
+    w: &mut impl Write,
+    h: &Header,
+    values: &BitSketch,
+    empty: &[u64],
+    abundances: Option<&[u64]>,
+) -> io::Result<()> {
+    let mut w = CrcWriter { inner: w, crc: !0 };
+    w.write_all(&MAGIC)?;
+    w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    w.write_all(&[
+        h.kind,
+        h.hasher.kind,
+        h.hash_bits as u8,
+        h.b as u8,
+        h.flags,
+        0,
+    ])?;
+    w.write_all(&(h.k as u32).to_le_bytes())?;
+    w.write_all(&h.hasher.seed.to_le_bytes())?;
+    for x in [
+        h.s as u64,
+        h.scale,
+        h.len as u64,
+        h.kmers,
+        h.seq_len,
+        h.min_count as u64,
+    ] {
+        w.write_all(&x.to_le_bytes())?;
+    }
+    w.write_all(&(h.name.len() as u32).to_le_bytes())?;
+    w.write_all(h.name.as_bytes())?;
+    let path = &h.metadata.path;
+    w.write_all(&(path.len() as u32).to_le_bytes())?;
+    w.write_all(path.as_bytes())?;
+    for x in [h.metadata.records, h.metadata.gc] {
+        w.write_all(&x.unwrap_or(u64::MAX).to_le_bytes())?;
+    }
+
+    match values {
+        BitSketch::B64(v) | BitSketch::B1(v) => write_words(&mut w, v, |x| x.to_le_bytes())?,
+        BitSketch::B32(v) => write_words(&mut w, v, |x| x.to_le_bytes())?,
+        BitSketch::B16(v) => write_words(&mut w, v, |x| x.to_le_bytes())?,
+        BitSketch::B8(v) => write_words(&mut w, v, |x| x.to_le_bytes())?,
+    }
+    if h.flags & FLAG_EMPTY != 0 {
+        write_words(&mut w, empty, |x| x.to_le_bytes())?;
+    }
+    if let Some(abundances) = abundances {
+        write_words(&mut w, abundances, |x| x.to_le_bytes())?;
+    }
+
+    let crc = !w.crc;
+    w.inner.write_all(&crc.to_le_bytes())
+}
+
+/// The number of stored words, i.e. values, or groups of 64 values for `b=1`.
+fn num_words(values: &BitSketch) -> usize {
+    match values {
+        BitSketch::B64(v) | BitSketch::B1(v) => v.len(),
+        BitSketch::B32(v) => v.len(),
+        BitSketch::B16(v) => v.len(),
+        BitSketch::B8(v) => v.len(),
+    }
+}
+
+fn write_words<T: Copy, const N: usize>(
+    w: &mut CrcWriter<impl Write>,
+    words: &[T],
+    to_bytes: impl Fn(T) -> [u8; N],
+) -> io::Result<()> {
+    w.write_all(&(words.len() as u64).to_le_bytes())?;
+    let bytes = words.iter().flat_map(|&x| to_bytes(x)).collect::<Vec<_>>();
+    w.write_all(&bytes)
+}
+
+/// Read a sketch of the given kind, or of any kind when `kind` is `None`.
+#[allow(clippy::type_complexity)]
+fn read_sketch(
+    r: &mut impl Read,
+    kind: Option<u8>,
+) -> io::Result<(Header, BitSketch, Vec<u64>, Option<Vec<u64>>)> {
+    let mut r = CrcReader { inner: r, crc: !0 };
+    let magic: [u8; 4] = r.read_array()?;
+    if magic != MAGIC {
+        return Err(invalid("Not a simd-sketch sketch: invalid magic bytes."));
+    }
+    let version = u16::from_le_bytes(r.read_array()?);
+    if version != FORMAT_VERSION {
+        return Err(invalid(format!(
+            "Unsupported sketch format version {version}; expected {FORMAT_VERSION}."
+        )));
+    }
+    let [read_kind, hash_id, hash_bits, b, flags, _reserved] = r.read_array()?;
+    match kind {
+        Some(kind) if read_kind != kind => {
+            return Err(invalid(format!(
+                "Expected sketch kind {kind}, but found {read_kind}."
+            )));
+        }
+        None if read_kind > KIND_SCALED => {
+            return Err(invalid(format!("Unknown sketch kind {read_kind}.")));
+        }
+        _ => {}
+    }
+    if !matches!(hash_bits, 32 | 64) || !matches!(b, 1 | 8 | 16 | 32 | 64) || b > hash_bits {
+        return Err(invalid(format!(
+            "Invalid bit widths: hash_bits={hash_bits}, b={b}."
+        )));
+    }
+    let k = u32::from_le_bytes(r.read_array()?) as usize;
+    let seed = u64::from_le_bytes(r.read_array()?);
+    let mut fields = [0u64; 6];
+    for x in &mut fields {
+        *x = u64::from_le_bytes(r.read_array()?);
+    }
+    let [s, scale, len, kmers, seq_len, min_count] = fields;
+    let name_len = u32::from_le_bytes(r.read_array()?) as usize;
+    let name = String::from_utf8(r.read_vec(name_len)?)
+        .map_err(|_| invalid("Sketch name is not valid UTF-8."))?;
+    let path_len = u32::from_le_bytes(r.read_array()?) as usize;
+    let path = String::from_utf8(r.read_vec(path_len)?)
+        .map_err(|_| invalid("Sketch path is not valid UTF-8."))?;
+    let mut read_count = || -> io::Result<_> {
+        let x = u64::from_le_bytes(r.read_array()?);
+        Ok((x != u64::MAX).then_some(x))
+    };
+    let metadata = Metadata {
+        path,
+        records: read_count()?,
+        gc: read_count()?,
+    };
+
+    let values = match b {
+        64 => BitSketch::B64(read_words(&mut r, u64::from_le_bytes)?),
+        32 => BitSketch::B32(read_words(&mut r, u32::from_le_bytes)?),
+        16 => BitSketch::B16(read_words(&mut r, u16::from_le_bytes)?),
+        8 => BitSketch::B8(read_words(&mut r, u8::from_le_bytes)?),
+        1 => BitSketch::B1(read_words(&mut r, u64::from_le_bytes)?),
+        _ => unreachable!(),
+    };
+    let empty = if flags & FLAG_EMPTY != 0 {
+        read_words(&mut r, u64::from_le_bytes)?
+    } else {
+        vec![]
+    };
+    let abundances = if flags & FLAG_ABUNDANCES != 0 {
+        if read_kind == KIND_BUCKET {
+            return Err(invalid("Bucket sketches can not store abundances."));
+        }
+        Some(read_words(&mut r, u64::from_le_bytes)?)
+    } else {
+        None
+    };
+
+    let crc = !r.crc;
+    let mut expected = [0; 4];
+    r.inner.read_exact(&mut expected)?;
+    if crc != u32::from_le_bytes(expected) {
+        return Err(invalid("Sketch checksum mismatch."));
+    }
+
+    let header = Header {
+        kind: read_kind,
+        hasher: HasherId {
+            kind: hash_id,
+            seed,
+        },
+        hash_bits: hash_bits as usize,
+        b: b as usize,
+        flags,
+        k,
+        s: s as usize,
+        scale,
+        len: len as usize,
+        kmers,
+        seq_len,
+        min_count: min_count as usize,
+        name,
+        metadata,
+    };
+    Ok((header, values, empty, abundances))
+}
+
+fn read_words<T, const N: usize>(
+    r: &mut CrcReader<impl Read>,
+    from_bytes: impl Fn([u8; N]) -> T,
+) -> io::Result<Vec<T>> {
+    let n = u64::from_le_bytes(r.read_array()?) as usize;
+    let bytes = r.read_vec(
+        n.checked_mul(N)
+            .ok_or_else(|| invalid("Too many values."))?,
+    )?;
+    Ok(bytes
+        .chunks_exact(N)
+        .map(|x| from_bytes(x.try_into().unwrap()))
+        .collect())
+}
+
+struct CrcWriter<W> {
+    inner: W,
+    crc: u32,
+}
+
+impl<W: Write> CrcWriter<W> {
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.crc = crc32_update(self.crc, bytes);
+        self.inner.write_all(bytes)
+    }
+}
+
+struct CrcReader<R> {
+    inner: R,
+    crc: u32,
+}
+
+impl<R: Read> CrcReader<R> {
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.inner.read_exact(&mut bytes)?;
+        self.crc = crc32_update(self.crc, &bytes);
+        Ok(bytes)
+    }
+
+    /// Read `n` bytes, without trusting `n` for the initial allocation.
+    fn read_vec(&mut self, n: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(n.min(1 << 20));
+        (&mut self.inner).take(n as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != n {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.crc = crc32_update(self.crc, &bytes);
+        Ok(bytes)
+    }
+}
+
+/// CRC-32 (IEEE 802.3) lookup table.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            j += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &b in bytes {
+        crc = CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+#[test]
+fn roundtrip() {
+    use packed_seq::SeqVec;
+
+    assert_eq!(!crc32_update(!0, b"123456789"), 0xcbf43926);
+
+    let seq = packed_seq::PackedSeqVec::random(5000);
+    for (b, hash_bits) in [(1, 32), (8, 32), (16, 64), (32, 32), (64, 64)] {
+        let mut sketcher = crate::Sketcher::new_rc(15, 256, b);
+        sketcher.hash_bits = hash_bits;
+        sketcher.filter_empty = true;
+        sketcher.densify = b == 8;
+        sketcher.min_count = if b == 16 { 2 } else { 1 };
+        sketcher.b_bit_bottom = b >= 16;
+        sketcher.track_abundance = b == hash_bits;
+        let sketcher = sketcher.with_hasher(crate::Seeded::new(crate::KmerMix, 7));
+
+        let mut bottom = sketcher.bottom_sketch(seq.as_slice());
+        bottom.name = "seq ✓".to_string();
+        bottom.metadata = Metadata {
+            path: "data/seq ✓.fa".to_string(),
+            records: Some(3),
+            gc: None,
+        };
+        let bucket = sketcher.sketch(seq.as_slice());
+        let scaled = sketcher.scaled_sketch(seq.as_slice(), 10);
+        assert_eq!(bottom.abundances().is_some(), b == hash_bits);
+
+        // Multiple sketches can be written to one stream.
+        let mut bytes = vec![];
+        bottom.write(&mut bytes).unwrap();
+        bucket.write(&mut bytes).unwrap();
+        scaled.write(&mut bytes).unwrap();
+        let mut r = &bytes[..];
+        assert_eq!(BottomSketch::read(&mut r).unwrap(), bottom);
+        assert_eq!(BucketSketch::read(&mut r).unwrap(), bucket);
+        assert_eq!(ScaledSketch::read(&mut r).unwrap(), scaled);
+        assert!(r.is_empty());
+        let any = read_all(&mut &bytes[..]).unwrap();
+        assert_eq!(any, [bottom.clone().into(), bucket.into(), scaled.into()]);
+
+        // Wrong kinds, truncation, and corruption are detected.
+        let bytes = bottom.to_bytes();
+        assert!(BucketSketch::from_bytes(&bytes).is_err());
+        assert!(BottomSketch::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        for i in [0, 5, 20, bytes.len() / 2, bytes.len() - 1] {
+            let mut corrupt = bytes.clone();
+            corrupt[i] ^= 0x10;
+            assert!(BottomSketch::from_bytes(&corrupt).is_err(), "{i}");
+        }
+    }
+
+    // Other versions are rejected.
+    let mut bytes = crate::Sketcher::new_rc(21, 256, 32)
+        .bottom_sketch(seq.as_slice())
+        .to_bytes();
+    bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    let len = bytes.len() - 4;
+    let crc = !crc32_update(!0, &bytes[..len]);
+    bytes[len..].copy_from_slice(&crc.to_le_bytes());
+    let err = BottomSketch::from_bytes(&bytes).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Unsupported sketch format version")
+    );
+}
+
+/// The parts of a serialized sketch, to test validation of modified headers.
+#[cfg(test)]
+fn parts(bytes: &[u8]) -> (Header, BitSketch, Vec<u64>, Option<Vec<u64>>) {
+    read_sketch(&mut &bytes[..], None).unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn invalid_len() {
+    use packed_seq::SeqVec;
+    let seq = packed_seq::PackedSeqVec::random(5000);
+    let bottom = crate::Sketcher::new_rc(15, 256, 32).bottom_sketch(seq.as_slice());
+    let (mut h, values, _, abundances) = parts(&bottom.to_bytes());
+    h.len = h.s + 1;
+    let err = BottomSketch::from_parts(h, values, abundances).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[cfg(test)]
+#[test]
+fn invalid_value_count() {
+    use packed_seq::SeqVec;
+    let seq = packed_seq::PackedSeqVec::random(5000);
+    let mut sketcher = crate::Sketcher::new_rc(15, 256, 32);
+    let bottom = sketcher.bottom_sketch(seq.as_slice());
+    sketcher.b_bit_bottom = true;
+    sketcher.b = 16;
+    let b_bit = sketcher.bottom_sketch(seq.as_slice());
+    // Full hashes are padded to `s`, and b-bit values have length `len`.
+    for bytes in [bottom.to_bytes(), b_bit.to_bytes()] {
+        let (mut h, values, _, abundances) = parts(&bytes);
+        h.s -= 1;
+        h.len = h.len.min(h.s) - 1;
+        let err = BottomSketch::from_parts(h, values, abundances).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    for b in [1, 8, 32] {
+        let bucket = crate::Sketcher::new_rc(15, 256, b).sketch(seq.as_slice());
+        let (mut h, values, empty, _) = parts(&bucket.to_bytes());
+        h.s += 64;
+        let err = BucketSketch::from_parts(h, values, empty).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn invalid_empty_mask() {
+    use packed_seq::SeqVec;
+    let seq = packed_seq::PackedSeqVec::random(100);
+    let mut sketcher = crate::Sketcher::new_rc(15, 256, 32);
+    sketcher.filter_empty = true;
+    let bucket = sketcher.sketch(seq.as_slice());
+    let (h, values, mut empty, _) = parts(&bucket.to_bytes());
+    assert!(h.flags & FLAG_EMPTY != 0);
+    assert_eq!(empty.len(), 4);
+    empty.pop();
+    let err = BucketSketch::from_parts(h, values, empty).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[cfg(test)]
+#[test]
+fn invalid_k() {
+    use packed_seq::SeqVec;
+    let seq = packed_seq::PackedSeqVec::random(5000);
+    let sketcher = crate::Sketcher::new_rc(15, 256, 32).with_hasher(crate::KmerMix);
+    for k in [0, 17] {
+        let (mut h, values, _, abundances) =
+            parts(&sketcher.bottom_sketch(seq.as_slice()).to_bytes());
+        h.k = k;
+        let err = BottomSketch::from_parts(h, values, abundances).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let (mut h, values, empty, _) = parts(&sketcher.sketch(seq.as_slice()).to_bytes());
+        h.k = k;
+        let err = BucketSketch::from_parts(h, values, empty).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let (mut h, values, _, abundances) =
+            parts(&sketcher.scaled_sketch(seq.as_slice(), 10).to_bytes());
+        h.k = k;
+        let err = ScaledSketch::from_parts(h, values, abundances).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,413 @@
+//! Import and export of [sourmash](https://sourmash.readthedocs.io/) JSON signatures.
+//!
+//! A signature file contains a list of signatures, each of which holds one or more MinHash sketches
+//! of the same input. Sketches with `num` set are bottom sketches and map to [`BottomSketch`],
+//! and sketches with `max_hash` set are FracMinHash sketches and map to [`ScaledSketch`].
+//!
+//! sourmash hashes canonical k-mers with 64-bit MurmurHash3 and seed 42.
+//! Sketches created by [`Sketcher::new_sourmash`](crate::Sketcher::new_sourmash) use the same hashes,
+//! and can be compared directly against imported signatures.
+
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    BitSketch, BottomSketch, HasherId, KmerHasher, Metadata, Murmur3, ScaledSketch, bottom_kmers,
+    max_hash_for_scale,
+};
+
+const SEED: u64 = 42;
+const HASH_FUNCTION: &str = "0.murmur64";
+
+/// A sourmash signature: sketches of a single input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    /// The name of the input. Sketches read from a signature have this name too.
+    pub name: String,
+    /// The file the input was read from.
+    pub filename: String,
+    pub sketches: Vec<Sketch>,
+}
+
+/// A sourmash MinHash sketch with either a fixed number of hashes or a fixed scale.
+/// Abundances, if any, are stored in the sketch itself.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Sketch {
+    Bottom(BottomSketch),
+    Scaled(ScaledSketch),
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonSignature {
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    email: String,
+    hash_function: String,
+    #[serde(default)]
+    filename: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    license: String,
+    signatures: Vec<JsonMinHash>,
+    version: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonMinHash {
+    num: u64,
+    ksize: usize,
+    seed: u64,
+    max_hash: u64,
+    mins: Vec<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    abundances: Option<Vec<u64>>,
+    #[serde(default)]
+    md5sum: String,
+    molecule: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(JsonSignature),
+    Many(Vec<JsonSignature>),
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// The md5sum of a sketch as computed by sourmash: of `k`, followed by all hashes, in decimal.
+fn md5sum(k: usize, hashes: &[u64]) -> String {
+    let mut ctx = md5::Context::new();
+    ctx.consume(k.to_string());
+    for h in hashes {
+        ctx.consume(h.to_string());
+    }
+    format!("{:x}", ctx.finalize())
+}
+
+/// Read all signatures from a sourmash JSON signature file.
+///
+/// Only DNA sketches using 64-bit MurmurHash3 with seed 42 are supported.
+pub fn read_signatures(r: impl Read) -> io::Result<Vec<Signature>> {
+    let json = match serde_json::from_reader(r).map_err(|e| invalid(e.to_string()))? {
+        OneOrMany::One(sig) => vec![sig],
+        OneOrMany::Many(sigs) => sigs,
+    };
+    json.into_iter().map(from_json).collect()
+}
+
+/// Write signatures as a sourmash JSON signature file.
+///
+/// All sketches must use the (unseeded) [`Murmur3`] hasher with 64-bit canonical hashes, store full hashes,
+/// and have as many abundances as hashes, if any.
+pub fn write_signatures(signatures: &[Signature], w: impl Write) -> io::Result<()> {
+    let json = signatures
+        .iter()
+        .map(to_json)
+        .collect::<io::Result<Vec<_>>>()?;
+    serde_json::to_writer(w, &json).map_err(io::Error::from)
+}
+
+fn from_json(sig: JsonSignature) -> io::Result<Signature> {
+    if sig.hash_function != HASH_FUNCTION {
+        return Err(invalid(format!(
+            "Unsupported sourmash hash function {}; only {HASH_FUNCTION} is supported.",
+            sig.hash_function
+        )));
+    }
+    let name = sig.name.unwrap_or_default();
+    let sketches = sig
+        .signatures
+        .into_iter()
+        .map(|mh| minhash_from_json(mh, &name))
+        .collect::<io::Result<_>>()?;
+    Ok(Signature {
+        name,
+        filename: sig.filename.unwrap_or_default(),
+        sketches,
+    })
+}
+
+fn minhash_from_json(mh: JsonMinHash, name: &str) -> io::Result<Sketch> {
+    if !mh.molecule.eq_ignore_ascii_case("dna") {
+        return Err(invalid(format!(
+            "Unsupported sourmash molecule type {}; only DNA is supported.",
+            mh.molecule
+        )));
+    }
+    if mh.seed != SEED {
+        return Err(invalid(format!(
+            "Unsupported sourmash seed {}; only {SEED} is supported.",
+            mh.seed
+        )));
+    }
+    if mh.ksize == 0 {
+        return Err(invalid("Invalid sourmash ksize 0."));
+    }
+    if mh
+        .abundances
+        .as_ref()
+        .is_some_and(|a| a.len() != mh.mins.len())
+    {
+        return Err(invalid("Number of sourmash abundances and hashes differ."));
+    }
+
+    // Sort the hashes, and their abundances with them.
+    let has_abundances = mh.abundances.is_some();
+    let abundances = mh
+        .abundances
+        .unwrap_or_default()
+        .into_iter()
+        .chain(std::iter::repeat(1));
+    let mut pairs = std::iter::zip(mh.mins, abundances).collect::<Vec<_>>();
+    pairs.sort_unstable_by_key(|&(h, _)| h);
+    pairs.dedup_by_key(|&mut (h, _)| h);
+    let abundances =
+        |pairs: &[(u64, u64)]| has_abundances.then(|| pairs.iter().map(|&(_, a)| a).collect());
+
+    let sketch = if mh.num > 0 {
+        let s = mh.num as usize;
+        pairs.truncate(s);
+        let mut hashes = pairs.iter().map(|&(h, _)| h).collect::<Vec<_>>();
+        let len = hashes.len();
+        let kmers = bottom_kmers(&hashes, s);
+        hashes.resize(s, u64::MAX);
+        Sketch::Bottom(BottomSketch {
+            rc: true,
+            k: mh.ksize,
+            s,
+            b: 64,
+            hash_bits: 64,
+            hasher: Murmur3.id(),
+            len,
+            kmers,
+            name: name.to_string(),
+            metadata: Metadata::default(),
+            seq_len: 0,
+            min_count: 1,
+            bottom: BitSketch::B64(hashes),
+            abundances: abundances(&pairs),
+        })
+    } else if mh.max_hash > 0 {
+        let scale = (u64::MAX as f64 / mh.max_hash as f64).round().max(1.0) as u64;
+        let bound = max_hash_for_scale(64, scale);
+        // sourmash's Python and Rust code round `MAX / scale` differently, by at most one.
+        if bound.abs_diff(mh.max_hash) > 1 {
+            return Err(invalid(format!(
+                "sourmash max_hash {} does not correspond to an integer scale.",
+                mh.max_hash
+            )));
+        }
+        if pairs.last().is_some_and(|&(h, _)| h > bound) {
+            return Err(invalid("sourmash hash larger than max_hash."));
+        }
+        Sketch::Scaled(ScaledSketch {
+            rc: true,
+            k: mh.ksize,
+            hash_bits: 64,
+            hasher: Murmur3.id(),
+            scale,
+            name: name.to_string(),
+            metadata: Metadata::default(),
+            seq_len: 0,
+            min_count: 1,
+            hashes: BitSketch::B64(pairs.iter().map(|&(h, _)| h).collect()),
+            abundances: abundances(&pairs),
+        })
+    } else {
+        return Err(invalid("sourmash sketch has neither num nor max_hash set."));
+    };
+    Ok(sketch)
+}
+
+fn to_json(sig: &Signature) -> io::Result<JsonSignature> {
+    Ok(JsonSignature {
+        class: "sourmash_signature".to_string(),
+        email: String::new(),
+        hash_function: HASH_FUNCTION.to_string(),
+        filename: Some(sig.filename.clone()),
+        name: Some(sig.name.clone()),
+        license: "CC0".to_string(),
+        signatures: sig
+            .sketches
+            .iter()
+            .map(minhash_to_json)
+            .collect::<io::Result<_>>()?,
+        version: 0.4,
+    })
+}
+
+fn minhash_to_json(sketch: &Sketch) -> io::Result<JsonMinHash> {
+    let (num, max_hash, k, hashes, abundances) = match sketch {
+        Sketch::Bottom(s) if is_sourmash(s.rc, s.hasher, s.hash_bits) && s.b == 64 => {
+            (s.s as u64, 0, s.k, &b64(&s.bottom)[..s.len], s.abundances())
+        }
+        Sketch::Scaled(s) if is_sourmash(s.rc, s.hasher, s.hash_bits) => {
+            (0, s.max_hash(), s.k, b64(&s.hashes), s.abundances())
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Only sketches created by `Sketcher::new_sourmash` can be written as sourmash signatures.",
+            ));
+        }
+    };
+    if abundances.is_some_and(|a| a.len() != hashes.len()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Number of abundances and hashes differ.",
+        ));
+    }
+    Ok(JsonMinHash {
+        num,
+        ksize: k,
+        seed: SEED,
+        max_hash,
+        mins: hashes.to_vec(),
+        abundances: abundances.map(<[u64]>::to_vec),
+        md5sum: md5sum(k, hashes),
+        molecule: "DNA".to_string(),
+    })
+}
+
+fn b64(values: &BitSketch) -> &[u64] {
+    match values {
+        BitSketch::B64(v) => v,
+        _ => unreachable!("64-bit sketches store 64-bit values"),
+    }
+}
+
+/// Whether sketches with these parameters contain sourmash hashes.
+fn is_sourmash(rc: bool, hasher: HasherId, hash_bits: usize) -> bool {
+    rc && hasher == Murmur3.id() && hash_bits == 64
+}
+
+#[cfg(test)]
+#[test]
+fn roundtrip() {
+    use packed_seq::SeqVec;
+
+    assert_eq!(md5sum(31, &[1, 23]), format!("{:x}", md5::compute("31123")));
+
+    let seq = packed_seq::AsciiSeqVec::random(20000);
+    let mut sketcher = crate::Sketcher::new_sourmash(21, 500);
+    let mut bottom = sketcher.bottom_sketch(seq.as_slice());
+    bottom.name = "seq".to_string();
+    sketcher.track_abundance = true;
+    let mut scaled = sketcher.scaled_sketch(seq.as_slice(), 100);
+    scaled.name = "seq".to_string();
+    assert!(scaled.abundances().is_some());
+    let sig = Signature {
+        name: "seq".to_string(),
+        filename: "seq.fa".to_string(),
+        sketches: vec![
+            Sketch::Bottom(bottom.clone()),
+            Sketch::Scaled(scaled.clone()),
+        ],
+    };
+
+    let mut bytes = vec![];
+    write_signatures(std::slice::from_ref(&sig), &mut bytes).unwrap();
+    let mut read = read_signatures(&bytes[..]).unwrap();
+    assert_eq!(read.len(), 1);
+    // Sequence lengths are not stored.
+    for sketch in &mut read[0].sketches {
+        match sketch {
+            Sketch::Bottom(s) => s.seq_len = bottom.seq_len,
+            Sketch::Scaled(s) => s.seq_len = scaled.seq_len,
+        }
+    }
+    assert_eq!(read[0], sig);
+
+    // A single signature object is accepted too.
+    let json = String::from_utf8(bytes).unwrap();
+    let single = read_signatures(&json.as_bytes()[1..json.len() - 1]).unwrap();
+    assert_eq!(single.len(), 1);
+
+    // Unsupported hashes are rejected.
+    let other = json.replace(HASH_FUNCTION, "0.murmur32");
+    assert!(read_signatures(other.as_bytes()).is_err());
+    let nthash = crate::Sketcher::new_rc(21, 500, 32).bottom_sketch(seq.as_slice());
+    let sig = Signature {
+        name: String::new(),
+        filename: String::new(),
+        sketches: vec![Sketch::Bottom(nthash)],
+    };
+    assert!(write_signatures(&[sig], &mut vec![]).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn sourmash_fixture() {
+    // A self-generated signature in sourmash's format (not written by sourmash itself), of the sequence
+    // in sourmash's `test_dna_mh`, with a bottom sketch with `num=5` and a scaled sketch with `scaled=2`
+    // and abundances, for `k=4`.
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/sourmash/test_dna_mh.sig"
+    );
+    let json = std::fs::read(path).unwrap();
+    let sigs = read_signatures(&json[..]).unwrap();
+    assert_eq!(sigs.len(), 1);
+    assert_eq!(sigs[0].name, "test_dna_mh");
+    assert_eq!(sigs[0].filename, "test_dna_mh.fa");
+
+    let seq = packed_seq::AsciiSeq(b"ATGGCAGTGACGATGCCAG");
+    let mut sketcher = crate::Sketcher::new_sourmash(4, 5);
+    sketcher.track_abundance = true;
+    let [Sketch::Bottom(bottom), Sketch::Scaled(scaled)] = &sigs[0].sketches[..] else {
+        panic!("Expected a bottom and a scaled sketch.");
+    };
+
+    // The hashes asserted by sourmash's `test_dna_mh`.
+    let hashes = &b64(&bottom.bottom)[..bottom.len];
+    assert_eq!(hashes[..2], [726311917625663847, 3697418565283905118]);
+    let expected = sketcher.bottom_sketch(seq);
+    assert_eq!(hashes, &b64(&expected.bottom)[..expected.len]);
+    assert_eq!(bottom.k, 4);
+    assert_eq!(bottom.s, 5);
+
+    let expected = sketcher.scaled_sketch(seq, 2);
+    assert_eq!(scaled.scale, 2);
+    assert_eq!(b64(&scaled.hashes), b64(&expected.hashes));
+    assert_eq!(scaled.abundances(), expected.abundances());
+    assert_eq!(scaled.abundances().unwrap().iter().sum::<u64>(), 8);
+
+    // Writing the signature back gives the same hashes and md5sums.
+    let mut bytes = vec![];
+    write_signatures(&sigs, &mut bytes).unwrap();
+    let written: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    let original: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    for (a, b) in std::iter::zip(
+        written[0]["signatures"].as_array().unwrap(),
+        original[0]["signatures"].as_array().unwrap(),
+    ) {
+        for key in ["num", "ksize", "seed", "mins", "abundances", "md5sum"] {
+            assert_eq!(a[key], b[key], "{key}");
+        }
+    }
+
+    // As in sourmash, a hash equal to `max_hash` is kept.
+    let max_hash = scaled.max_hash();
+    assert_eq!(max_hash, 1 << 63);
+    let mut edited = original.clone();
+    let mh = &mut edited[0]["signatures"][1];
+    mh["mins"].as_array_mut().unwrap().push(max_hash.into());
+    mh["abundances"].as_array_mut().unwrap().push(1.into());
+    let sigs = read_signatures(edited.to_string().as_bytes()).unwrap();
+    let Sketch::Scaled(edited_scaled) = &sigs[0].sketches[1] else {
+        panic!("Expected a scaled sketch.");
+    };
+    assert_eq!(edited_scaled.len(), scaled.len() + 1);
+    assert_eq!(b64(&edited_scaled.hashes).last(), Some(&max_hash));
+
+    // A `max_hash` that does not correspond to an integer scale is rejected.
+    let mut edited = original.clone();
+    edited[0]["signatures"][1]["max_hash"] = ((u64::MAX as f64 / 1.4) as u64).into();
+    assert!(read_signatures(edited.to_string().as_bytes()).is_err());
+}
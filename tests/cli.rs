@@ -0,0 +1,118 @@
+//! End-to-end tests of the `simd-sketch` binary.
+
+use std::{path::PathBuf, process::Command};
+
+/// Run the binary and return its stdout.
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_simd-sketch"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{args:?}: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// A temporary directory with copies of the Mash fixtures, and `c.fa`, a renamed copy of `a.fa`.
+fn inputs(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("simd-sketch-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let data = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/mash");
+    for (from, to) in [("a.fa", "a.fa"), ("b.fa", "b.fa"), ("a.fa", "c.fa")] {
+        std::fs::copy(data.join(from), dir.join(to)).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn sketch_dist_triangle() {
+    let dir = inputs("cli");
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    let (a, b, c, out) = (path("a.fa"), path("b.fa"), path("c.fa"), path("all.ssk"));
+
+    // Mash-compatible parameters, as for `mash sketch -k 21 -s 100`.
+    let params = [
+        "-k",
+        "21",
+        "-s",
+        "100",
+        "--hasher",
+        "murmur3",
+        "--hash-bits",
+        "64",
+    ];
+    run(&[&["sketch", &a, &b, &c, "-o", &out][..], &params].concat());
+
+    let info = run(&["info", &out]);
+    assert_eq!(info.lines().count(), 4, "{info}");
+    assert!(
+        info.lines()
+            .nth(1)
+            .unwrap()
+            .starts_with(&format!("{a}\tbottom\t21\t"))
+    );
+
+    // The self-generated `tests/data/mash/k21.dist` gives `0.0227107 45/100` for `a.fa` and `b.fa`.
+    let dist = run(&["dist", &out, "--format", "mash"]);
+    let lines = dist
+        .lines()
+        .map(|l| l.split('\t').collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "{dist}");
+    let line = |x: &str, y: &str| {
+        lines
+            .iter()
+            .find(|l| l[..2] == [x, y] || l[..2] == [y, x])
+            .unwrap_or_else(|| panic!("{x} {y}: {dist}"))
+    };
+    let ab = line(&a, &b);
+    assert!(
+        (ab[2].parse::<f32>().unwrap() - 0.0227107).abs() < 1e-6,
+        "{ab:?}"
+    );
+    assert_eq!(ab[4], "45/100");
+    let ac = line(&a, &c);
+    assert_eq!((ac[2], ac[4]), ("0", "100/100"));
+
+    // The PHYLIP matrix has the same distances.
+    let triangle = run(&["triangle", &out]);
+    let rows = triangle.lines().collect::<Vec<_>>();
+    assert_eq!(rows[0], "3");
+    assert_eq!(rows[1], a);
+    let row = rows[2].split('\t').collect::<Vec<_>>();
+    assert_eq!(row[0], b);
+    assert!(
+        (row[1].parse::<f32>().unwrap() - 0.0227107).abs() < 1e-6,
+        "{row:?}"
+    );
+    assert_eq!(rows[3].split('\t').collect::<Vec<_>>(), [&c, "0", row[1]]);
+
+    // Each record of a file is sketched separately with `--individual`.
+    let records = path("records.fa");
+    let seqs = [&a, &b]
+        .map(|f| std::fs::read_to_string(f).unwrap())
+        .concat();
+    std::fs::write(&records, seqs).unwrap();
+    let out = path("records.ssk");
+    run(&[
+        &["sketch", &records, "--individual", "-o", &out][..],
+        &params,
+    ]
+    .concat());
+    let info = run(&["info", &out]);
+    let names = info
+        .lines()
+        .skip(1)
+        .map(|l| l.split('\t').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["a", "b"]);
+    let dist = run(&["dist", &out, "--format", "mash"]);
+    let line = dist.split('\t').collect::<Vec<_>>();
+    assert_eq!(line[..2], ["b", "a"], "{dist}");
+    assert_eq!(line[2], ab[2]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,286 @@
+#!/usr/bin/env python3
+"""Generate the Mash test fixtures in this directory.
+
+The fixtures are self-generated: they are NOT produced by a Mash binary.
+This script is a standalone reimplementation of `mash sketch` and `mash dist` (Mash 2.3),
+independent of simd-sketch, that emulates these commands:
+
+    mash sketch -k 21 -s 100 -o k21 a.fa b.fa
+    mash sketch -k 15 -s 100 -o k15 a.fa b.fa
+    mash dist k21.msh k21.msh > k21.dist
+    mash dist k15.msh k15.msh > k15.dist
+
+The `.msh` files are encoded following Mash's `MinHash.capnp` schema, with the fields
+allocated in the order in which Mash's `writeToCapnp` sets them.
+"""
+
+import math
+import random
+import struct
+
+M64 = (1 << 64) - 1
+M32 = (1 << 32) - 1
+SEED = 42
+
+
+def rotl64(x, r):
+    return ((x << r) | (x >> (64 - r))) & M64
+
+
+def rotl32(x, r):
+    return ((x << r) | (x >> (32 - r))) & M32
+
+
+def fmix64(k):
+    k ^= k >> 33
+    k = (k * 0xFF51AFD7ED558CCD) & M64
+    k ^= k >> 33
+    k = (k * 0xC4CEB9FE1A85EC53) & M64
+    k ^= k >> 33
+    return k
+
+
+def fmix32(h):
+    h ^= h >> 16
+    h = (h * 0x85EBCA6B) & M32
+    h ^= h >> 13
+    h = (h * 0xC2B2AE35) & M32
+    h ^= h >> 16
+    return h
+
+
+def murmur3_x64_128(key, seed):
+    """The first 64 bits of MurmurHash3_x64_128, as used by Mash for k > 16."""
+    c1, c2 = 0x87C37B91114253D5, 0x4CF5AD432745937F
+    h1 = h2 = seed
+    n = len(key) // 16
+    for i in range(n):
+        k1, k2 = struct.unpack_from("<QQ", key, 16 * i)
+        k1 = (rotl64((k1 * c1) & M64, 31) * c2) & M64
+        h1 ^= k1
+        h1 = (rotl64(h1, 27) + h2) & M64
+        h1 = (h1 * 5 + 0x52DCE729) & M64
+        k2 = (rotl64((k2 * c2) & M64, 33) * c1) & M64
+        h2 ^= k2
+        h2 = (rotl64(h2, 31) + h1) & M64
+        h2 = (h2 * 5 + 0x38495AB5) & M64
+    tail = key[16 * n :]
+    k1 = int.from_bytes(tail[:8], "little")
+    k2 = int.from_bytes(tail[8:], "little")
+    if len(tail) > 8:
+        k2 = (rotl64((k2 * c2) & M64, 33) * c1) & M64
+        h2 ^= k2
+    if len(tail) > 0:
+        k1 = (rotl64((k1 * c1) & M64, 31) * c2) & M64
+        h1 ^= k1
+    h1 ^= len(key)
+    h2 ^= len(key)
+    h1 = (h1 + h2) & M64
+    h2 = (h2 + h1) & M64
+    h1 = fmix64(h1)
+    h2 = fmix64(h2)
+    h1 = (h1 + h2) & M64
+    return h1
+
+
+def murmur3_x86_32(key, seed):
+    """MurmurHash3_x86_32, as used by Mash for k <= 16."""
+    c1, c2 = 0xCC9E2D51, 0x1B873593
+    h = seed
+    n = len(key) // 4
+    for i in range(n):
+        (k,) = struct.unpack_from("<I", key, 4 * i)
+        k = (rotl32((k * c1) & M32, 15) * c2) & M32
+        h ^= k
+        h = (rotl32(h, 13) * 5 + 0xE6546B64) & M32
+    tail = key[4 * n :]
+    if tail:
+        k = int.from_bytes(tail, "little")
+        k = (rotl32((k * c1) & M32, 15) * c2) & M32
+        h ^= k
+    h ^= len(key)
+    return fmix32(h)
+
+
+def read_fasta(path):
+    seq = []
+    with open(path) as f:
+        for line in f:
+            if not line.startswith(">"):
+                seq.append(line.strip())
+    return "".join(seq)
+
+
+def sketch(seq, k, s):
+    """The `s` smallest distinct hashes of canonical k-mers without non-ACGT characters."""
+    seq = seq.upper()
+    comp = str.maketrans("ACGT", "TGCA")
+    hashes = set()
+    for i in range(len(seq) - k + 1):
+        kmer = seq[i : i + k]
+        if any(c not in "ACGT" for c in kmer):
+            continue
+        rc = kmer.translate(comp)[::-1]
+        kmer = min(kmer, rc).encode()
+        if k > 16:
+            hashes.add(murmur3_x64_128(kmer, SEED))
+        else:
+            hashes.add(murmur3_x86_32(kmer, SEED))
+    return sorted(hashes)[:s]
+
+
+def binomial_q(x, p, n):
+    """P(X > x) for X ~ B(n, p), i.e. `gsl_cdf_binomial_Q(x, p, n)`."""
+    if x >= n:
+        return 0.0
+    total = 0.0
+    for i in range(x + 1, n + 1):
+        ln = (
+            math.lgamma(n + 1)
+            - math.lgamma(i + 1)
+            - math.lgamma(n - i + 1)
+            + i * math.log(p)
+            + (n - i) * math.log1p(-p)
+        )
+        total += math.exp(ln)
+    return total
+
+
+def p_value(x, len_a, len_b, k, n):
+    if x == 0:
+        return 1.0
+    space = 4.0**k
+    pa = 1.0 / (1.0 + space / len_a)
+    pb = 1.0 / (1.0 + space / len_b)
+    r = pa * pb / (pa + pb - pa * pb)
+    return binomial_q(x - 1, r, n)
+
+
+def dist(a, b, s, k, len_a, len_b):
+    i = j = common = denom = 0
+    while denom < s and i < len(a) and j < len(b):
+        if a[i] == b[j]:
+            common += 1
+            i += 1
+            j += 1
+        elif a[i] < b[j]:
+            i += 1
+        else:
+            j += 1
+        denom += 1
+    if denom < s:
+        denom = min(s, denom + len(a) - i + len(b) - j)
+    jaccard = common / denom
+    if common == denom:
+        d = 0.0
+    elif common == 0:
+        d = 1.0
+    else:
+        d = -math.log(2 * jaccard / (1 + jaccard)) / k
+    return d, p_value(common, len_a, len_b, k, denom), common, denom
+
+
+class Builder:
+    """A single-segment Cap'n Proto message."""
+
+    def __init__(self):
+        self.words = bytearray()
+
+    def alloc(self, n):
+        pos = len(self.words) // 8
+        self.words += bytes(8 * n)
+        return pos
+
+    def set(self, offset, data):
+        self.words[offset : offset + len(data)] = data
+
+    def pointer(self, at, target, kind, hi):
+        offset = ((target - at - 1) << 2) & M32
+        self.set(8 * at, struct.pack("<Q", offset | kind | hi << 32))
+
+    def struct(self, at, data, ptrs):
+        pos = self.alloc(data + ptrs)
+        self.pointer(at, pos, 0, data | ptrs << 16)
+        return pos
+
+    def list(self, at, size, n, data):
+        pos = self.alloc((len(data) + 7) // 8)
+        self.set(8 * pos, data)
+        self.pointer(at, pos, 1, size | n << 3)
+
+    def text(self, at, text):
+        data = text.encode() + b"\0"
+        self.list(at, 2, len(data), data)
+
+
+def write_msh(path, k, s, refs):
+    m = Builder()
+    root = m.alloc(1)
+    mh = m.struct(root, 3, 4)
+    data, ptrs = 8 * mh, mh + 3
+    m.set(data + 0, struct.pack("<I", k))
+    # The seed is stored XOR'ed with its default 42.
+    m.set(data + 20, struct.pack("<I", SEED ^ 42))
+    m.set(data + 4, struct.pack("<I", 10000))
+    m.set(data + 8, struct.pack("<I", s))
+    # Concatenated, canonical, and not case-preserving.
+    m.set(data + 12, bytes([1]))
+    m.text(ptrs + 3, "ACGT")
+    ref_list = m.struct(ptrs + 2, 0, 1)
+    n = len(refs)
+    pos = m.alloc(1 + n * 9)
+    m.pointer(ref_list, pos, 1, 7 | (n * 9) << 3)
+    m.set(8 * pos, struct.pack("<Q", n << 2 | (2 | 7 << 16) << 32))
+    for i, (name, length, hashes) in enumerate(refs):
+        r = pos + 1 + 9 * i
+        rdata, rptrs = 8 * r, r + 2
+        m.text(rptrs + 2, name)
+        m.text(rptrs + 3, "")
+        m.set(rdata + 8, struct.pack("<Q", length))
+        if k > 16:
+            m.list(rptrs + 5, 5, len(hashes), struct.pack(f"<{len(hashes)}Q", *hashes))
+        else:
+            m.list(rptrs + 4, 4, len(hashes), struct.pack(f"<{len(hashes)}I", *hashes))
+    with open(path, "wb") as f:
+        f.write(struct.pack("<II", 0, len(m.words) // 8))
+        f.write(m.words)
+
+
+def write_fasta(path, name, seq):
+    with open(path, "w") as f:
+        f.write(f">{name}\n")
+        for i in range(0, len(seq), 60):
+            f.write(seq[i : i + 60] + "\n")
+
+
+def main():
+    rng = random.Random(42)
+    rand = lambda n: "".join(rng.choice("ACGT") for _ in range(n))
+    shared = rand(3000)
+    # `b` shares most of `a`, with some point mutations, a run of `N`s, and lowercase bases.
+    a = rand(1000) + shared
+    b = list(shared + rand(500))
+    for i in rng.sample(range(len(shared)), 30):
+        b[i] = rng.choice("ACGT".replace(b[i], ""))
+    b = "".join(b)
+    b = b[:1000] + "N" * 20 + b[1020:2000].lower() + b[2000:]
+    write_fasta("a.fa", "a", a)
+    write_fasta("b.fa", "b", b)
+
+    files = ["a.fa", "b.fa"]
+    for k in [21, 15]:
+        s = 100
+        refs = []
+        for name in files:
+            seq = read_fasta(name)
+            refs.append((name, len(seq), sketch(seq, k, s)))
+        write_msh(f"k{k}.msh", k, s, refs)
+        with open(f"k{k}.dist", "w") as f:
+            for ref in refs:
+                for query in refs:
+                    d, p, common, denom = dist(ref[2], query[2], s, k, ref[1], query[1])
+                    f.write(f"{ref[0]}\t{query[0]}\t{d:g}\t{p:g}\t{common}/{denom}\n")
+
+
+if __name__ == "__main__":
+    main()